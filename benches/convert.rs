@@ -0,0 +1,24 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rupantor::avro::AvroPhonetic;
+
+const PARAGRAPH: &str = "amader valObasa hoye gel ghas, kheye gel goru ar diye gelo ba^sh. \
+    ami banglay gan gai, ami banglar gan gai. ami amar amike cirodin ei banglay khu^je pai! ";
+
+fn bench_new(c: &mut Criterion) {
+    c.bench_function("avro/new", |b| b.iter(AvroPhonetic::new));
+}
+
+fn bench_convert(c: &mut Criterion) {
+    let avro = AvroPhonetic::new();
+    let document = PARAGRAPH.repeat(200);
+
+    c.bench_function("convert/word", |b| b.iter(|| avro.convert("bangladesh")));
+
+    let mut group = c.benchmark_group("convert");
+    group.throughput(Throughput::Bytes(document.len() as u64));
+    group.bench_function("document", |b| b.iter(|| avro.convert(&document)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_new, bench_convert);
+criterion_main!(benches);
@@ -0,0 +1,20 @@
+//! Compiles a Json grammar into the binary format.
+//!
+//! Usage: `cargo run --example compile_grammar <grammar.json> <grammar.bin>`
+use std::{env, fs, process};
+use rupantor::grammar::Grammar;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <grammar.json> <grammar.bin>", args[0]);
+        process::exit(1);
+    }
+
+    let json = fs::read_to_string(&args[1]).expect("Couldn't read the grammar");
+    let grammar = Grammar::from_json_str(&json).unwrap_or_else(|err| {
+        eprintln!("{}: {}", args[1], err);
+        process::exit(1);
+    });
+    fs::write(&args[2], grammar.to_bytes()).expect("Couldn't write the compiled grammar");
+}
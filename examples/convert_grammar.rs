@@ -0,0 +1,31 @@
+//! Converts a grammar between Json, TOML and YAML, picking the formats
+//! from the file extensions.
+//!
+//! Usage: `cargo run --features toml,yaml --example convert_grammar <input> <output>`
+use std::{env, fs, process};
+use rupantor::format::{self, Format};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <input> <output>", args[0]);
+        process::exit(1);
+    }
+
+    let formats: Vec<Format> = args[1..]
+        .iter()
+        .map(|path| {
+            Format::from_path(path).unwrap_or_else(|| {
+                eprintln!("{}: unknown or disabled grammar format", path);
+                process::exit(1);
+            })
+        })
+        .collect();
+
+    let text = fs::read_to_string(&args[1]).expect("Couldn't read the grammar");
+    let converted = format::convert(&text, formats[0], formats[1]).unwrap_or_else(|err| {
+        eprintln!("{}: {}", args[1], err);
+        process::exit(1);
+    });
+    fs::write(&args[2], converted).expect("Couldn't write the converted grammar");
+}
@@ -0,0 +1,19 @@
+//! Renders a grammar file in the compact text format.
+//!
+//! Usage: `cargo run --example render_dsl <grammar.json> <grammar.txt>`
+use std::{env, fs, process};
+use rupantor::grammar::Grammar;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <grammar> <grammar.txt>", args[0]);
+        process::exit(1);
+    }
+
+    let grammar = Grammar::load(&args[1]).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    fs::write(&args[2], grammar.to_dsl()).expect("Couldn't write the grammar");
+}
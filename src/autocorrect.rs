@@ -0,0 +1,367 @@
+//! Whole-word overrides of the phonetic conversion.
+//!
+//! Some words are spelled wrongly by the phonetic rules alone. An
+//! [`Autocorrect`](struct.Autocorrect.html) table maps such Roman words to
+//! fixed Bengali spellings and is consulted for every word before the
+//! remaining text is converted by the [`PhoneticParser`](../parser/struct.PhoneticParser.html).
+//!
+//! A table is written as tab separated lines of a Roman word and its
+//! Bengali spelling or, with the `json` feature, as a Json object of the
+//! same pairs.
+//!
+//! # Example
+//! ```rust
+//! use rupantor::avro::AvroPhonetic;
+//!
+//! let mut autocorrect = AvroPhonetic::new().autocorrect();
+//! autocorrect.load_tsv("computer\tকম্পিউটার\n").unwrap();
+//! assert_eq!(autocorrect.convert("(computer) kini."), "(কম্পিউটার) কিনি।");
+//! ```
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+#[cfg(feature = "json")]
+use serde_json::Value;
+use crate::parser::PhoneticParser;
+
+/// Converts text with a parser, replacing the words
+/// found in the table with their fixed spelling.
+#[derive(Clone)]
+pub struct Autocorrect {
+    parser: PhoneticParser,
+    /// Case normalized Roman words and their Bengali spelling.
+    entries: HashMap<String, String>,
+}
+
+/// Errors which can occur while loading an autocorrect table.
+#[derive(Debug)]
+pub enum AutocorrectError {
+    /// The text is not valid Json.
+    #[cfg(feature = "json")]
+    Syntax(serde_json::Error),
+    /// A Json value has the wrong type. The path is the key of the
+    /// entry, or empty for the table itself.
+    InvalidType { path: String, expected: &'static str },
+    /// A tab separated line is malformed. Lines are counted from 1.
+    InvalidLine { line: usize, reason: &'static str },
+}
+
+impl fmt::Display for AutocorrectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "json")]
+            AutocorrectError::Syntax(err) => write!(f, "invalid json: {}", err),
+            AutocorrectError::InvalidType { path, expected } if path.is_empty() => {
+                write!(f, "expected {}", expected)
+            }
+            AutocorrectError::InvalidType { path, expected } => {
+                write!(f, "{}: expected {}", path, expected)
+            }
+            AutocorrectError::InvalidLine { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl Error for AutocorrectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "json")]
+            AutocorrectError::Syntax(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl Autocorrect {
+    /// Creates an empty table in front of the given parser.
+    pub fn new(parser: PhoneticParser) -> Autocorrect {
+        Autocorrect {
+            parser,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Adds the entries of a table with one `roman<TAB>bengali` pair per
+    /// line. Empty lines are ignored. Nothing is added if a line is malformed.
+    pub fn load_tsv(&mut self, table: &str) -> Result<(), AutocorrectError> {
+        let mut entries = Vec::new();
+        for (index, line) in table.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let error = |reason| AutocorrectError::InvalidLine { line: index + 1, reason };
+            let (roman, bengali) = line.split_once('\t').ok_or_else(|| error("missing tab"))?;
+            if roman.trim().is_empty() {
+                return Err(error("empty word"));
+            }
+            entries.push((roman, bengali));
+        }
+
+        for (roman, bengali) in entries {
+            self.insert(roman, bengali);
+        }
+        Ok(())
+    }
+
+    /// Adds the entries of a Json object mapping Roman words to their
+    /// Bengali spelling. Nothing is added if the table is malformed.
+    #[cfg(feature = "json")]
+    pub fn load_json(&mut self, table: &str) -> Result<(), AutocorrectError> {
+        let value: Value = serde_json::from_str(table).map_err(AutocorrectError::Syntax)?;
+        let object = value.as_object().ok_or(AutocorrectError::InvalidType {
+            path: String::new(),
+            expected: "an object",
+        })?;
+
+        let entries = object
+            .iter()
+            .map(|(roman, bengali)| match bengali.as_str() {
+                Some(bengali) => Ok((roman, bengali)),
+                None => Err(AutocorrectError::InvalidType {
+                    path: roman.clone(),
+                    expected: "a string",
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (roman, bengali) in entries {
+            self.insert(roman, bengali);
+        }
+        Ok(())
+    }
+
+    /// Adds an entry, replacing an earlier one for the same word. The case
+    /// of the word matters only as far as it matters to the grammar.
+    pub fn insert(&mut self, roman: &str, bengali: &str) {
+        self.entries.insert(self.parser.fix_string(roman.trim()), bengali.to_string());
+    }
+
+    /// Removes the entry of a word, returning its Bengali spelling.
+    pub fn remove(&mut self, roman: &str) -> Option<String> {
+        self.entries.remove(&self.parser.fix_string(roman.trim()))
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the table has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Converts the given input string into Bengali.
+    ///
+    /// Every whitespace separated word found in the table is replaced by its
+    /// entry. A word which is not found is looked up again without the
+    /// punctuation of the grammar around it, which is then converted along
+    /// with the rest of the text. Escapes of the grammar also separate words,
+    /// and the text inside them is never replaced.
+    ///
+    /// The rest of the text is converted in one pass over the whole input,
+    /// so the rules next to a replaced word still see its letters.
+    pub fn convert(&self, input: &str) -> String {
+        let fixed = self.parser.fix_string(input);
+        let mut words = self.words(input).into_iter().peekable();
+        let mut output = String::with_capacity(input.len() * 3);
+        let mut cur = 0;
+
+        while cur < fixed.len() {
+            // A word is only replaced where the parser would start a step.
+            while words.next_if(|&(start, _, _)| start < cur).is_some() {}
+            if let Some((_, end, bengali)) = words.next_if(|&(start, _, _)| start == cur) {
+                output += bengali;
+                cur = end;
+                continue;
+            }
+
+            let step = self.parser.step(input, &fixed, cur);
+            output += &step.output;
+            cur = step.end;
+        }
+
+        output
+    }
+
+    /// Returns the bounds and entries of the words of the input
+    /// found in the table, in the order they appear.
+    fn words(&self, input: &str) -> Vec<(usize, usize, &str)> {
+        let mut words = Vec::new();
+
+        for (span_start, span_end) in self.unescaped(input) {
+            let mut rest = &input[span_start..span_end];
+            while !rest.is_empty() {
+                let word_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let word_start = span_end - rest.len();
+                if let Some((start, end, bengali)) = self.lookup(&rest[..word_len]) {
+                    words.push((word_start + start, word_start + end, bengali));
+                }
+
+                let after = &rest[word_len..];
+                let space_len = after.find(|c: char| !c.is_whitespace()).unwrap_or(after.len());
+                rest = &after[space_len..];
+            }
+        }
+
+        words
+    }
+
+    /// Finds the entry of the longest part of the word which leaves out
+    /// only punctuation at its ends, returning the bounds of the part.
+    fn lookup(&self, word: &str) -> Option<(usize, usize, &str)> {
+        let is_punctuation = |c: char| self.parser.is_punctuation(c);
+        let starts = std::iter::once(0).chain(
+            word.char_indices()
+                .take_while(|&(_, c)| is_punctuation(c))
+                .map(|(i, c)| i + c.len_utf8()),
+        );
+        let ends = std::iter::once(word.len()).chain(
+            word.char_indices()
+                .rev()
+                .take_while(|&(_, c)| is_punctuation(c))
+                .map(|(i, _)| i),
+        );
+
+        let ends: Vec<usize> = ends.collect();
+        let mut parts: Vec<(usize, usize)> = starts
+            .flat_map(|start| ends.iter().map(move |&end| (start, end)))
+            .filter(|&(start, end)| start < end)
+            .collect();
+        parts.sort_by_key(|&(start, end)| (start + word.len() - end, start));
+
+        parts.into_iter().find_map(|(start, end)| {
+            self.entries
+                .get(&self.parser.fix_string(&word[start..end]))
+                .map(|bengali| (start, end, bengali.as_str()))
+        })
+    }
+
+    /// Returns the spans of the input which are not part of an escape,
+    /// found by stepping through the input like the parser does.
+    fn unescaped(&self, input: &str) -> Vec<(usize, usize)> {
+        let fixed = self.parser.fix_string(input);
+        let mut spans = Vec::new();
+        let mut start = 0;
+        let mut cur = 0;
+        while cur < fixed.len() {
+            match self.parser.escape(input, cur) {
+                Some(escape) => {
+                    spans.push((start, cur));
+                    start = escape.end;
+                    cur = escape.end;
+                }
+                None => cur = self.parser.step(input, &fixed, cur).end,
+            }
+        }
+        spans.push((start, fixed.len()));
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avro::AvroPhonetic;
+
+    #[test]
+    fn test_convert() {
+        let avro = AvroPhonetic::new();
+        let mut autocorrect = avro.autocorrect();
+        autocorrect.load_tsv("computer\tকম্পিউটার\n\nAmra\tআমরা\n").unwrap();
+        assert_eq!(autocorrect.len(), 2);
+
+        let text = "amra  \"computer\", computers... amar computer";
+        assert_eq!(
+            autocorrect.convert(text),
+            format!("আমরা  \"কম্পিউটার{}কম্পিউটার", avro.convert("\", computers... amar "))
+        );
+        assert_eq!(autocorrect.convert("..."), avro.convert("..."));
+        assert_eq!(autocorrect.convert(""), "");
+
+        autocorrect.insert("kot", "কোট");
+        assert_eq!(autocorrect.convert("kot?"), "কোট?");
+        assert_eq!(autocorrect.remove("Kot"), Some("কোট".to_string()));
+        assert_eq!(autocorrect.convert("kot"), avro.convert("kot"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        use crate::grammar::Escape;
+        use crate::parser::PhoneticParser;
+        use super::Autocorrect;
+
+        let mut grammar = AvroPhonetic::new().grammar();
+        grammar.verbatim.push(Escape { open: "{".to_string(), close: "}".to_string() });
+        grammar.code_point = Some(Escape { open: "\\u{".to_string(), close: "}".to_string() });
+        let mut autocorrect = Autocorrect::new(PhoneticParser::from_grammar(grammar));
+        autocorrect.insert("computer", "কম্পিউটার");
+
+        assert_eq!(autocorrect.convert("ami {computer} kini"), "আমি computer কিনি");
+        assert_eq!(autocorrect.convert("ami {my computer} kini"), "আমি my computer কিনি");
+        assert_eq!(autocorrect.convert("computer {computer"), "কম্পিউটার computer");
+        assert_eq!(autocorrect.convert("\\u{9F3}computer"), "৳কম্পিউটার");
+    }
+
+    #[test]
+    fn test_context() {
+        use crate::builder::GrammarBuilder;
+        use crate::grammar::Scope;
+        use super::Autocorrect;
+
+        let parser = GrammarBuilder::new()
+            .vowels("aeiou")
+            .consonants("bcdfghjklmnpqrstvwxyz")
+            .numbers("0123456789")
+            .pattern("k", "ক")
+            .pattern("a", "আ")
+            .rule(|r| r.prefix(Scope::Consonant).replace("া"))
+            .pattern("-", "-")
+            .rule(|r| r.suffix(Scope::Consonant).replace(""))
+            .rule(|r| r.prefix(Scope::Consonant).replace("–"))
+            .parser()
+            .unwrap();
+        let mut autocorrect = Autocorrect::new(parser);
+        autocorrect.insert("kak", "কাক!");
+
+        // The rules around the word see its letters, not the end of the text.
+        assert_eq!(autocorrect.convert("-kak-"), "কাক!–");
+        assert_eq!(autocorrect.convert("-kak- -"), "কাক!– -");
+
+        // Punctuation keys of the grammar may be part of a word.
+        let mut autocorrect = AvroPhonetic::new().autocorrect();
+        autocorrect.insert("etc.", "ইত্যাদি");
+        autocorrect.insert("o`", "ও");
+        assert_eq!(autocorrect.convert("etc., ami"), "ইত্যাদি, আমি");
+        assert_eq!(autocorrect.convert("(o`)"), "(ও)");
+        assert_eq!(autocorrect.convert("etc"), AvroPhonetic::new().convert("etc"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut autocorrect = AvroPhonetic::new().autocorrect();
+
+        let err = autocorrect.load_tsv("ami\tআমি\namra আমরা").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: missing tab");
+        let err = autocorrect.load_tsv(" \tআমি").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: empty word");
+        assert!(autocorrect.is_empty());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_load_json() {
+        use super::AutocorrectError;
+
+        let mut autocorrect = AvroPhonetic::new().autocorrect();
+
+        autocorrect.load_json(r#"{ "ami": "আমি", "computer": "কম্পিউটার" }"#).unwrap();
+        assert_eq!(autocorrect.convert("ami computer"), "আমি কম্পিউটার");
+
+        let err = autocorrect.load_json(r#"{ "tumi": 1 }"#).unwrap_err();
+        assert_eq!(err.to_string(), "tumi: expected a string");
+        let err = autocorrect.load_json("[]").unwrap_err();
+        assert_eq!(err.to_string(), "expected an object");
+        assert!(matches!(autocorrect.load_json("{"), Err(AutocorrectError::Syntax(_))));
+        assert_eq!(autocorrect.len(), 2);
+    }
+}
@@ -1,17 +1,52 @@
-use serde_json;
+use std::sync::{OnceLock, PoisonError, RwLock};
+use crate::autocorrect::Autocorrect;
+use crate::dictionary::{Dictionary, Suggestions};
+use crate::grammar::{Grammar, GrammarError};
 use crate::parser::PhoneticParser;
+use crate::reverse::ReverseParser;
+use crate::session::ConversionSession;
+use crate::spans::Segment;
+use crate::trace::Trace;
 
 /// Converts text into Bengali by using Avro Phonetic
 /// transliteration method.
+///
+/// Cloning an `AvroPhonetic` is cheap and it can be shared between threads.
+/// Use [`global`](#method.global) to share a single instance in the process.
+///
+/// The grammar of an instance can be swapped while it is shared, see
+/// [`set_grammar`](#method.set_grammar) and [`update`](#method.update).
+/// Every conversion uses the grammar which was current when it started.
 pub struct AvroPhonetic {
-    parser: PhoneticParser,
+    parser: RwLock<PhoneticParser>,
 }
 
 impl AvroPhonetic {
     /// Creates a new AvroPhonetic instance.
+    ///
+    /// The Avro Phonetic grammar is embedded in the precompiled binary
+    /// format, so no Json is parsed.
     pub fn new() -> AvroPhonetic {
-        let rule = serde_json::from_str(include_str!("AvroPhonetic.json")).unwrap();
-        AvroPhonetic { parser: PhoneticParser::new(&rule) }
+        let grammar = Grammar::from_bytes(include_bytes!("AvroPhonetic.bin"))
+            .expect("the embedded Avro Phonetic grammar is valid");
+        AvroPhonetic::from_parser(PhoneticParser::from_grammar(grammar))
+    }
+
+    fn from_parser(parser: PhoneticParser) -> AvroPhonetic {
+        AvroPhonetic { parser: RwLock::new(parser) }
+    }
+
+    /// Returns the process-wide AvroPhonetic instance, which is
+    /// created on first use.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rupantor::avro::AvroPhonetic;
+    /// assert_eq!(AvroPhonetic::global().convert("rasT"), "রাস্ট");
+    /// ```
+    pub fn global() -> &'static AvroPhonetic {
+        static GLOBAL: OnceLock<AvroPhonetic> = OnceLock::new();
+        GLOBAL.get_or_init(AvroPhonetic::new)
     }
 
     /// Converts the input text into Bengali by using Avro Phonetic method.
@@ -21,15 +56,210 @@ impl AvroPhonetic {
     /// # use rupantor::avro::AvroPhonetic;
     /// assert_eq!(AvroPhonetic::new().convert("rasT"), "রাস্ট");
     /// ```
+    pub fn convert(&self, input: &str) -> String {
+        self.parser().convert(input)
+    }
+
+    /// Returns the current parser. Later changes of the grammar
+    /// of this instance don't affect the returned parser.
+    ///
+    /// The lock is only held while the parser is cloned, which is cheap,
+    /// so conversions don't hold up a swap of the grammar.
+    pub fn parser(&self) -> PhoneticParser {
+        self.parser.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Returns the current grammar.
+    ///
+    /// # Example
+    /// A parser for Avro Phonetic which copies text in braces unconverted:
+    /// ```rust
+    /// # use rupantor::avro::AvroPhonetic;
+    /// use rupantor::grammar::Escape;
+    /// use rupantor::parser::PhoneticParser;
+    ///
+    /// let mut grammar = AvroPhonetic::new().grammar();
+    /// grammar.verbatim.push(Escape { open: "{".to_string(), close: "}".to_string() });
+    /// let parser = PhoneticParser::try_from_grammar(grammar).unwrap();
+    /// assert_eq!(parser.convert("ami {Rust} shikhi"), "আমি Rust শিখি");
+    /// ```
+    pub fn grammar(&self) -> Grammar {
+        self.parser().grammar().clone()
+    }
+
+    /// Replaces the grammar after checking it like
+    /// [`PhoneticParser::try_from_grammar`](../parser/struct.PhoneticParser.html#method.try_from_grammar).
+    /// Conversions which already started finish with the previous grammar.
+    pub fn set_grammar(&self, grammar: Grammar) -> Result<(), GrammarError> {
+        let parser = PhoneticParser::try_from_grammar(grammar)?;
+        *self.parser.write().unwrap_or_else(PoisonError::into_inner) = parser;
+        Ok(())
+    }
+
+    /// Edits the parser in place, e.g. with
+    /// [`PhoneticParser::add_pattern`](../parser/struct.PhoneticParser.html#method.add_pattern),
+    /// and returns the result of `edit`. No other edit of this instance
+    /// runs at the same time, and conversions starting meanwhile wait for
+    /// it, so `edit` must not use this instance itself.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rupantor::avro::AvroPhonetic;
+    /// use rupantor::grammar::Pattern;
+    ///
+    /// let avro = AvroPhonetic::new();
+    /// avro.update(|parser| parser.add_pattern(Pattern {
+    ///     find: "qq".to_string(),
+    ///     replace: "ক্ক".to_string(),
+    ///     alternatives: Vec::new(),
+    ///     rules: Vec::new(),
+    /// })).unwrap();
+    /// assert_eq!(avro.convert("aqq"), "আক্ক");
+    /// ```
+    pub fn update<R, F: FnOnce(&mut PhoneticParser) -> R>(&self, edit: F) -> R {
+        edit(&mut self.parser.write().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Converts `input` into at most `limit` candidate outputs, the most
+    /// plausible first, e.g. for the candidate window of an input method.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rupantor::avro::AvroPhonetic;
+    /// let avro = AvroPhonetic::new();
+    /// assert_eq!(avro.convert_candidates("sap", 3), vec!["সাপ", "শাপ", "ষাপ"]);
+    /// ```
+    pub fn convert_candidates(&self, input: &str, limit: usize) -> Vec<String> {
+        self.parser().convert_candidates(input, limit)
+    }
+
+    /// Converts `input` and returns the segments mapping input
+    /// positions to output positions, see [`Segment`](../spans/struct.Segment.html).
+    pub fn convert_with_spans(&self, input: &str) -> (String, Vec<Segment>) {
+        self.parser().convert_with_spans(input)
+    }
+
+    /// Converts `input` and records how every chunk of it was converted,
+    /// see [`Trace`](../trace/struct.Trace.html).
+    pub fn convert_traced(&self, input: &str) -> Trace {
+        self.parser().convert_traced(input)
+    }
+
+    /// Converts `input` and looks up at most `limit` words of the
+    /// dictionary which it can stand for, see
+    /// [`Dictionary::lookup`](../dictionary/struct.Dictionary.html#method.lookup).
+    pub fn suggest(&self, input: &str, dictionary: &Dictionary, limit: usize) -> Suggestions {
+        let parser = self.parser();
+        Suggestions {
+            conversion: parser.convert(input),
+            words: dictionary.lookup(&parser, input, limit),
+        }
+    }
+
+    /// Creates an empty [`Autocorrect`](../autocorrect/struct.Autocorrect.html)
+    /// table in front of Avro Phonetic.
+    pub fn autocorrect(&self) -> Autocorrect {
+        Autocorrect::new(self.parser())
+    }
+
+    /// Starts an incremental conversion session, see
+    /// [`ConversionSession`](../session/struct.ConversionSession.html).
+    pub fn session(&self) -> ConversionSession {
+        ConversionSession::new(self.parser())
+    }
+}
+
+/// The clone starts with the current grammar and is changed independently.
+impl Clone for AvroPhonetic {
+    fn clone(&self) -> Self {
+        AvroPhonetic::from_parser(self.parser())
+    }
+}
+
+impl Default for AvroPhonetic {
+    fn default() -> Self {
+        AvroPhonetic::new()
+    }
+}
+
+/// Converts Bengali text back into Avro Phonetic Roman text.
+///
+/// The produced Roman text is converted by [`AvroPhonetic`](struct.AvroPhonetic.html)
+/// into the same Bengali text, except for words which are too ambiguous to
+/// be verified, see [`ReverseParser::convert`](../reverse/struct.ReverseParser.html#method.convert).
+#[derive(Clone)]
+pub struct BengaliToRoman {
+    parser: ReverseParser,
+}
+
+impl BengaliToRoman {
+    /// Creates a new BengaliToRoman instance.
+    pub fn new() -> BengaliToRoman {
+        BengaliToRoman { parser: ReverseParser::new(AvroPhonetic::new().parser()) }
+    }
+
+    /// Converts the Bengali input text into Avro Phonetic Roman text.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rupantor::avro::{AvroPhonetic, BengaliToRoman};
+    /// let roman = BengaliToRoman::new().convert("আমি");
+    /// assert_eq!(roman, "ami");
+    /// assert_eq!(AvroPhonetic::new().convert(&roman), "আমি");
+    /// ```
     pub fn convert(&self, input: &str) -> String {
         self.parser.convert(input)
     }
 }
 
+impl Default for BengaliToRoman {
+    fn default() -> Self {
+        BengaliToRoman::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::AvroPhonetic;
 
+    #[test]
+    fn test_global() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<AvroPhonetic>();
+
+        assert!(std::ptr::eq(AvroPhonetic::global(), AvroPhonetic::global()));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| AvroPhonetic::global().convert("ami")))
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), AvroPhonetic::new().convert("ami"));
+        }
+    }
+
+    #[test]
+    fn test_set_grammar() {
+        let avro = AvroPhonetic::new();
+        let before = avro.parser();
+        let mut grammar = avro.grammar();
+        grammar.patterns.retain(|pattern| pattern.find != "k");
+        avro.set_grammar(grammar).unwrap();
+
+        assert_eq!(avro.convert("ki"), "kি");
+        assert_eq!(before.convert("ki"), "কি");
+        assert_eq!(avro.clone().convert("ki"), avro.convert("ki"));
+
+        let removed = avro.update(|parser| parser.remove_pattern("kh"));
+        assert_eq!(removed.unwrap().replace, "খ");
+        assert_eq!(avro.grammar().patterns.len(), before.grammar().patterns.len() - 2);
+        assert_eq!(AvroPhonetic::new().convert("ki"), "কি");
+
+        let mut invalid = avro.grammar();
+        invalid.patterns[0].find.clear();
+        assert_eq!(avro.set_grammar(invalid).unwrap_err().to_string(), "patterns[0].find: must not be empty");
+        assert_eq!(avro.convert("ki"), "kি");
+    }
+
     #[test]
     fn test_avro() {
         let parser = AvroPhonetic::new();
@@ -0,0 +1,362 @@
+//! Building grammars in code.
+//!
+//! A [`GrammarBuilder`](struct.GrammarBuilder.html) creates a grammar from
+//! typed method calls instead of Json. Every call is validated right away:
+//! the first error, e.g. a rule using a class which was not declared before,
+//! is returned by [`build`](struct.GrammarBuilder.html#method.build) with the
+//! path it would have in a Json grammar.
+//!
+//! # Example
+//! ```rust
+//! use rupantor::builder::GrammarBuilder;
+//! use rupantor::grammar::Scope;
+//!
+//! let parser = GrammarBuilder::new()
+//!     .vowels("aeiou")
+//!     .consonants("bcdfghjklmnpqrstvwxyz")
+//!     .numbers("0123456789")
+//!     .pattern("k", "ক")
+//!     .pattern("kh", "খ")
+//!     .pattern("a", "আ")
+//!     .rule(|r| r.prefix(Scope::Consonant).replace("া"))
+//!     .parser()
+//!     .unwrap();
+//! assert_eq!(parser.convert("akha"), "আখা");
+//! ```
+use crate::grammar::{Escape, Grammar, GrammarError, Match, MatchType, Pattern, Rule, Scope, BUILTIN_SCOPES};
+use crate::parser::PhoneticParser;
+
+/// Builds a [`Grammar`](../grammar/struct.Grammar.html) step by step.
+#[derive(Debug)]
+pub struct GrammarBuilder {
+    grammar: Grammar,
+    vowels: bool,
+    consonants: bool,
+    numbers: bool,
+    /// The first error.
+    error: Option<GrammarError>,
+}
+
+/// Builds a [`Rule`](../grammar/struct.Rule.html) of a pattern.
+#[derive(Debug, Clone)]
+pub struct RuleBuilder {
+    rule: Rule,
+}
+
+impl GrammarBuilder {
+    /// Creates a builder for an empty grammar. The vowels, consonants and
+    /// numbers must be set before building it.
+    pub fn new() -> GrammarBuilder {
+        GrammarBuilder {
+            grammar: Grammar {
+                patterns: Vec::new(),
+                vowel: String::new(),
+                consonant: String::new(),
+                number: String::new(),
+                punctuation: None,
+                number_boundary: true,
+                classes: Default::default(),
+                case_sensitive: String::new(),
+                verbatim: Vec::new(),
+                code_point: None,
+            },
+            vowels: false,
+            consonants: false,
+            numbers: false,
+            error: None,
+        }
+    }
+
+    /// Sets the characters which are considered vowels.
+    pub fn vowels(mut self, vowels: &str) -> Self {
+        self.grammar.vowel = vowels.to_string();
+        self.vowels = true;
+        self
+    }
+
+    /// Sets the characters which are considered consonants.
+    pub fn consonants(mut self, consonants: &str) -> Self {
+        self.grammar.consonant = consonants.to_string();
+        self.consonants = true;
+        self
+    }
+
+    /// Sets the characters which are considered numbers.
+    pub fn numbers(mut self, numbers: &str) -> Self {
+        self.grammar.number = numbers.to_string();
+        self.numbers = true;
+        self
+    }
+
+    /// Sets the characters whose case is significant. None by default.
+    pub fn case_sensitive(mut self, characters: &str) -> Self {
+        self.grammar.case_sensitive = characters.to_string();
+        self
+    }
+
+    /// Sets the characters which are considered punctuation.
+    pub fn punctuation(mut self, punctuation: &str) -> Self {
+        self.grammar.punctuation = Some(punctuation.to_string());
+        self
+    }
+
+    /// Sets whether numbers are word boundaries. `true` by default.
+    pub fn number_boundary(mut self, number_boundary: bool) -> Self {
+        self.grammar.number_boundary = number_boundary;
+        self
+    }
+
+    /// Declares a character class, usable as `Scope::Class(name)` by
+    /// the rules which are added afterwards.
+    pub fn class(mut self, name: &str, characters: &str) -> Self {
+        if BUILTIN_SCOPES.contains(&name) {
+            self.fail(GrammarError::ReservedClass { path: format!("classes.{}", name) });
+        }
+        self.grammar.classes.insert(name.to_string(), characters.to_string());
+        self
+    }
+
+    /// Adds the delimiters of a verbatim escape.
+    pub fn verbatim(mut self, open: &str, close: &str) -> Self {
+        let path = format!("verbatim[{}]", self.grammar.verbatim.len());
+        let escape = self.escape(&path, open, close);
+        self.grammar.verbatim.push(escape);
+        self
+    }
+
+    /// Sets the delimiters of a code point escape.
+    pub fn code_point(mut self, open: &str, close: &str) -> Self {
+        self.grammar.code_point = Some(self.escape("codepoint", open, close));
+        self
+    }
+
+    /// Adds a pattern replacing `find` with `replace`.
+    pub fn pattern(mut self, find: &str, replace: &str) -> Self {
+        if find.is_empty() {
+            let path = format!("patterns[{}].find", self.grammar.patterns.len());
+            self.fail(GrammarError::EmptyFind { path });
+        }
+        self.grammar.patterns.push(Pattern {
+            find: find.to_string(),
+            replace: replace.to_string(),
+            alternatives: Vec::new(),
+            rules: Vec::new(),
+        });
+        self
+    }
+
+    /// Adds an alternative replacement to the last added pattern.
+    pub fn alternative(mut self, alternative: &str) -> Self {
+        match self.grammar.patterns.last_mut() {
+            Some(pattern) => pattern.alternatives.push(alternative.to_string()),
+            None => self.fail(GrammarError::NoPattern { path: "alternatives".to_string() }),
+        }
+        self
+    }
+
+    /// Adds a rule, built by the given function, to the last added pattern.
+    pub fn rule<F: FnOnce(RuleBuilder) -> RuleBuilder>(mut self, build: F) -> Self {
+        let rule = build(RuleBuilder {
+            rule: Rule {
+                matches: Vec::new(),
+                replace: String::new(),
+                alternatives: Vec::new(),
+            },
+        })
+        .rule;
+        let index = match self.grammar.patterns.len() {
+            0 => {
+                self.fail(GrammarError::NoPattern { path: "rules".to_string() });
+                return self;
+            }
+            len => len - 1,
+        };
+
+        let path = format!("patterns[{}].rules[{}]", index, self.grammar.patterns[index].rules.len());
+        let invalid = rule.matches.iter().enumerate().find_map(|(i, _match)| match &_match.scope {
+            Scope::Class(name) if !self.grammar.classes.contains_key(name) => Some(GrammarError::UnknownScope {
+                path: format!("{}.matches[{}].scope", path, i),
+                value: name.clone(),
+            }),
+            _ => _match.misplaced().map(|(scope, expected)| GrammarError::MisplacedScope {
+                path: format!("{}.matches[{}].type", path, i),
+                scope,
+                expected,
+            }),
+        });
+        if let Some(error) = invalid {
+            self.fail(error);
+        }
+        self.grammar.patterns[index].rules.push(rule);
+        self
+    }
+
+    /// Returns the grammar, or the first error found while building it.
+    /// The finished grammar is validated as a whole too, which also
+    /// checks a grammar the builder was created from.
+    pub fn build(self) -> Result<Grammar, GrammarError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        for (set, path) in [(self.vowels, "vowel"), (self.consonants, "consonant"), (self.numbers, "number")] {
+            if !set {
+                return Err(GrammarError::MissingField { path: path.to_string() });
+            }
+        }
+        self.grammar.validate()?;
+        Ok(self.grammar)
+    }
+
+    /// Builds the grammar and creates a parser from it.
+    pub fn parser(self) -> Result<PhoneticParser, GrammarError> {
+        self.build().map(PhoneticParser::from_grammar)
+    }
+
+    fn escape(&mut self, path: &str, open: &str, close: &str) -> Escape {
+        for (key, delimiter) in [("open", open), ("close", close)] {
+            if delimiter.is_empty() {
+                self.fail(GrammarError::EmptyDelimiter { path: format!("{}.{}", path, key) });
+            }
+        }
+        Escape {
+            open: open.to_string(),
+            close: close.to_string(),
+        }
+    }
+
+    fn fail(&mut self, error: GrammarError) {
+        self.error.get_or_insert(error);
+    }
+}
+
+impl Default for GrammarBuilder {
+    fn default() -> Self {
+        GrammarBuilder::new()
+    }
+}
+
+/// Continues building an existing grammar, e.g. to add patterns to Avro Phonetic.
+impl From<Grammar> for GrammarBuilder {
+    fn from(grammar: Grammar) -> Self {
+        GrammarBuilder {
+            grammar,
+            vowels: true,
+            consonants: true,
+            numbers: true,
+            error: None,
+        }
+    }
+}
+
+impl RuleBuilder {
+    /// Requires the text before the pattern to be in `scope`.
+    pub fn prefix(self, scope: Scope) -> Self {
+        self.condition(MatchType::Prefix, scope, false)
+    }
+
+    /// Requires the text before the pattern not to be in `scope`.
+    pub fn prefix_not(self, scope: Scope) -> Self {
+        self.condition(MatchType::Prefix, scope, true)
+    }
+
+    /// Requires the text after the pattern to be in `scope`.
+    pub fn suffix(self, scope: Scope) -> Self {
+        self.condition(MatchType::Suffix, scope, false)
+    }
+
+    /// Requires the text after the pattern not to be in `scope`.
+    pub fn suffix_not(self, scope: Scope) -> Self {
+        self.condition(MatchType::Suffix, scope, true)
+    }
+
+    /// Sets the replacement of the rule. Empty by default.
+    pub fn replace(mut self, replace: &str) -> Self {
+        self.rule.replace = replace.to_string();
+        self
+    }
+
+    /// Adds an alternative replacement of the rule.
+    pub fn alternative(mut self, alternative: &str) -> Self {
+        self.rule.alternatives.push(alternative.to_string());
+        self
+    }
+
+    fn condition(mut self, kind: MatchType, scope: Scope, negative: bool) -> Self {
+        self.rule.matches.push(Match { kind, scope, negative });
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avro::AvroPhonetic;
+    use super::*;
+
+    fn builder() -> GrammarBuilder {
+        GrammarBuilder::new()
+            .vowels("aeiou")
+            .consonants("bcdfghjklmnpqrstvwxyz")
+            .numbers("0123456789")
+    }
+
+    #[test]
+    fn test_build() {
+        let grammar = builder()
+            .case_sensitive("O")
+            .class("aspirate", "h")
+            .verbatim("{", "}")
+            .pattern("o", "অ")
+            .alternative("ও")
+            .rule(|r| r.prefix(Scope::Consonant).suffix_not(Scope::Class("aspirate".to_string())))
+            .rule(|r| r.prefix(Scope::Exact("`".to_string())).replace("ও").alternative("অ"))
+            .build()
+            .unwrap();
+
+        assert_eq!(grammar.case_sensitive, "O");
+        assert_eq!(grammar.patterns[0].alternatives, vec!["ও"]);
+        assert_eq!(grammar.patterns[0].rules[0], Rule {
+            matches: vec![
+                Match { kind: MatchType::Prefix, scope: Scope::Consonant, negative: false },
+                Match { kind: MatchType::Suffix, scope: Scope::Class("aspirate".to_string()), negative: true },
+            ],
+            replace: String::new(),
+            alternatives: Vec::new(),
+        });
+        assert_eq!(grammar.patterns[0].rules[1].alternatives, vec!["অ"]);
+        assert_eq!(grammar.verbatim[0].close, "}");
+
+        let avro = AvroPhonetic::new();
+        let parser = GrammarBuilder::from(avro.grammar())
+            .pattern("qq", "ক্ক")
+            .parser()
+            .unwrap();
+        assert_eq!(parser.convert("aqq"), "আক্ক");
+        assert_eq!(parser.convert("ami"), avro.convert("ami"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |builder: GrammarBuilder| builder.build().unwrap_err().to_string();
+
+        assert_eq!(error(GrammarBuilder::new().vowels("a").numbers("1")), "consonant: missing field");
+        assert_eq!(error(builder().pattern("k", "ক").pattern("", "")), "patterns[1].find: must not be empty");
+        assert_eq!(error(builder().rule(|r| r.replace("x"))), "rules: added before any pattern");
+        assert_eq!(error(builder().alternative("x")), "alternatives: added before any pattern");
+        assert_eq!(
+            error(builder().pattern("k", "ক").rule(|r| r.prefix(Scope::Class("aspirate".to_string()))).class("aspirate", "h")),
+            "patterns[0].rules[0].matches[0].scope: unknown scope `aspirate`"
+        );
+        assert_eq!(error(builder().class("vowel", "aeiou")), "classes.vowel: is the name of a built-in scope");
+        assert_eq!(error(builder().verbatim("{", "}").verbatim("<", "")), "verbatim[1].close: must not be empty");
+
+        // The first error is kept.
+        assert_eq!(error(builder().code_point("", "").pattern("", "")), "codepoint.open: must not be empty");
+
+        // A grammar the builder starts from is validated too.
+        let mut grammar = builder().pattern("k", "ক").build().unwrap();
+        grammar.classes.insert("start".to_string(), "a".to_string());
+        assert_eq!(error(GrammarBuilder::from(grammar.clone())), "classes.start: is the name of a built-in scope");
+        let err = GrammarBuilder::from(grammar).pattern("kh", "খ").parser().err().unwrap();
+        assert_eq!(err.to_string(), "classes.start: is the name of a built-in scope");
+    }
+}
@@ -0,0 +1,402 @@
+//! Compact binary format for grammars.
+//!
+//! Loading a grammar from this format does not need any parsing besides
+//! reading length prefixed strings, so it is much cheaper than loading
+//! the Json grammar. [`AvroPhonetic`](../avro/struct.AvroPhonetic.html)
+//! embeds its grammar in this format.
+//!
+//! The format is:
+//!
+//! * the magic bytes `RPNT` and a version byte,
+//! * the `vowel`, `consonant`, `number` and `casesensitive` strings,
+//! * the `punctuation` string, if any, as a list of at most one string,
+//!   and the `numberboundary` flag as a byte,
+//! * the character classes: name and characters,
+//! * the verbatim escapes and the code point escape, if any, as a list
+//!   of at most one escape; an escape is its `open` and `close` strings,
+//! * the patterns: `find`, `replace`, the alternatives and the rules,
+//! * every rule: `replace`, the alternatives and the matches,
+//! * every match: a tag byte and, for class scopes, the name of the class
+//!   and, for `exact` scopes, the value or the list of values.
+//!
+//! Strings are stored as a little endian `u32` byte length followed by the
+//! UTF-8 bytes and lists as a little endian `u32` count followed by the items.
+use std::collections::BTreeMap;
+use std::str;
+use crate::grammar::{Escape, Grammar, GrammarError, Match, MatchType, Pattern, Rule, Scope, BUILTIN_SCOPES};
+
+const MAGIC: &[u8] = b"RPNT";
+const VERSION: u8 = 5;
+
+// Bits of a match tag byte. The scope is stored in the upper bits.
+const SUFFIX: u8 = 0b01;
+const NEGATIVE: u8 = 0b10;
+const SCOPE_SHIFT: u8 = 2;
+
+const PUNCTUATION: u8 = 0;
+const VOWEL: u8 = 1;
+const CONSONANT: u8 = 2;
+const NUMBER: u8 = 3;
+const EXACT: u8 = 4;
+const WHITESPACE: u8 = 5;
+const START: u8 = 6;
+const END: u8 = 7;
+const CLASS: u8 = 8;
+const EXACT_ANY: u8 = 9;
+
+impl Grammar {
+    /// Serializes the grammar into the compact binary format.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rupantor::avro::AvroPhonetic;
+    /// # use rupantor::grammar::Grammar;
+    /// let grammar = AvroPhonetic::new().grammar();
+    /// let bytes = grammar.to_bytes();
+    /// assert_eq!(Grammar::from_bytes(&bytes).unwrap(), grammar);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer { bytes: MAGIC.to_vec() };
+        writer.bytes.push(VERSION);
+
+        writer.string(&self.vowel);
+        writer.string(&self.consonant);
+        writer.string(&self.number);
+        writer.string(&self.case_sensitive);
+        writer.strings(self.punctuation.as_slice());
+        writer.bytes.push(self.number_boundary as u8);
+
+        writer.count(self.classes.len());
+        for (name, characters) in &self.classes {
+            writer.string(name);
+            writer.string(characters);
+        }
+
+        writer.escapes(&self.verbatim);
+        writer.escapes(self.code_point.as_slice());
+
+        writer.count(self.patterns.len());
+        for pattern in &self.patterns {
+            writer.string(&pattern.find);
+            writer.string(&pattern.replace);
+            writer.strings(&pattern.alternatives);
+            writer.count(pattern.rules.len());
+            for rule in &pattern.rules {
+                writer.string(&rule.replace);
+                writer.strings(&rule.alternatives);
+                writer.count(rule.matches.len());
+                for _match in &rule.matches {
+                    writer.tag(_match);
+                }
+            }
+        }
+
+        writer.bytes
+    }
+
+    /// Loads a grammar from the binary format produced by
+    /// [`to_bytes`](#method.to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Grammar, GrammarError> {
+        let mut reader = Reader { bytes, offset: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(reader.error(0, "not a grammar"));
+        }
+        if reader.byte()? != VERSION {
+            return Err(reader.error(MAGIC.len(), "unsupported version"));
+        }
+
+        let vowel = reader.string()?;
+        let consonant = reader.string()?;
+        let number = reader.string()?;
+        let case_sensitive = reader.string()?;
+        let offset = reader.offset;
+        let mut punctuation = reader.strings()?;
+        if punctuation.len() > 1 {
+            return Err(reader.error(offset, "more than one punctuation class"));
+        }
+        let offset = reader.offset;
+        let number_boundary = match reader.byte()? {
+            0 => false,
+            1 => true,
+            _ => return Err(reader.error(offset, "invalid flag")),
+        };
+
+        let count = reader.count()?;
+        let mut classes = BTreeMap::new();
+        for _ in 0..count {
+            let offset = reader.offset;
+            let name = reader.string()?;
+            if BUILTIN_SCOPES.contains(&name.as_str()) {
+                return Err(reader.error(offset, "reserved class name"));
+            }
+            classes.insert(name, reader.string()?);
+        }
+
+        let verbatim = reader.escapes()?;
+        let offset = reader.offset;
+        let mut code_point = reader.escapes()?;
+        if code_point.len() > 1 {
+            return Err(reader.error(offset, "more than one code point escape"));
+        }
+
+        let count = reader.count()?;
+        let mut patterns = Vec::with_capacity(count);
+        for _ in 0..count {
+            let offset = reader.offset;
+            let find = reader.string()?;
+            if find.is_empty() {
+                return Err(reader.error(offset, "empty find"));
+            }
+            let replace = reader.string()?;
+            let alternatives = reader.strings()?;
+
+            let count = reader.count()?;
+            let mut rules = Vec::with_capacity(count);
+            for _ in 0..count {
+                let replace = reader.string()?;
+                let alternatives = reader.strings()?;
+                let count = reader.count()?;
+                let mut matches = Vec::with_capacity(count);
+                for _ in 0..count {
+                    matches.push(reader.tag(&classes)?);
+                }
+                rules.push(Rule {
+                    matches,
+                    replace,
+                    alternatives,
+                });
+            }
+            patterns.push(Pattern {
+                find,
+                replace,
+                alternatives,
+                rules,
+            });
+        }
+
+        if reader.offset != bytes.len() {
+            return Err(reader.error(reader.offset, "trailing bytes"));
+        }
+
+        Ok(Grammar {
+            patterns,
+            vowel,
+            consonant,
+            number,
+            punctuation: punctuation.pop(),
+            number_boundary,
+            classes,
+            case_sensitive,
+            verbatim,
+            code_point: code_point.pop(),
+        })
+    }
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn count(&mut self, count: usize) {
+        self.bytes.extend_from_slice(&(count as u32).to_le_bytes());
+    }
+
+    fn string(&mut self, string: &str) {
+        self.count(string.len());
+        self.bytes.extend_from_slice(string.as_bytes());
+    }
+
+    fn strings(&mut self, strings: &[String]) {
+        self.count(strings.len());
+        for string in strings {
+            self.string(string);
+        }
+    }
+
+    fn escapes(&mut self, escapes: &[Escape]) {
+        self.count(escapes.len());
+        for escape in escapes {
+            self.string(&escape.open);
+            self.string(&escape.close);
+        }
+    }
+
+    fn tag(&mut self, _match: &Match) {
+        let mut tag = match _match.scope {
+            Scope::Punctuation => PUNCTUATION,
+            Scope::Vowel => VOWEL,
+            Scope::Consonant => CONSONANT,
+            Scope::Number => NUMBER,
+            Scope::Whitespace => WHITESPACE,
+            Scope::Start => START,
+            Scope::End => END,
+            Scope::Class(_) => CLASS,
+            Scope::Exact(_) => EXACT,
+            Scope::ExactAny(_) => EXACT_ANY,
+        } << SCOPE_SHIFT;
+        if _match.kind == MatchType::Suffix {
+            tag |= SUFFIX;
+        }
+        if _match.negative {
+            tag |= NEGATIVE;
+        }
+
+        self.bytes.push(tag);
+        match &_match.scope {
+            Scope::Class(name) => self.string(name),
+            Scope::Exact(value) => self.string(value),
+            Scope::ExactAny(values) => self.strings(values),
+            _ => {}
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, offset: usize, reason: &'static str) -> GrammarError {
+        GrammarError::InvalidBinary { offset, reason }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], GrammarError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.saturating_add(len))
+            .ok_or_else(|| self.error(self.offset, "unexpected end"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, GrammarError> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn count(&mut self) -> Result<usize, GrammarError> {
+        let mut count = [0; 4];
+        count.copy_from_slice(self.take(4)?);
+        let count = u32::from_le_bytes(count) as usize;
+        // Every item takes at least one byte, which keeps a corrupt
+        // count from allocating huge amounts of memory.
+        if count > self.bytes.len() - self.offset {
+            return Err(self.error(self.offset - 4, "count exceeds the data"));
+        }
+        Ok(count)
+    }
+
+    fn string(&mut self) -> Result<String, GrammarError> {
+        let len = self.count()?;
+        let offset = self.offset;
+        str::from_utf8(self.take(len)?)
+            .map(str::to_string)
+            .map_err(|_| self.error(offset, "invalid UTF-8"))
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, GrammarError> {
+        let count = self.count()?;
+        let mut strings = Vec::with_capacity(count);
+        for _ in 0..count {
+            strings.push(self.string()?);
+        }
+        Ok(strings)
+    }
+
+    fn escapes(&mut self) -> Result<Vec<Escape>, GrammarError> {
+        let count = self.count()?;
+        let mut escapes = Vec::with_capacity(count);
+        for _ in 0..count {
+            let offset = self.offset;
+            let open = self.string()?;
+            let close = self.string()?;
+            if open.is_empty() || close.is_empty() {
+                return Err(self.error(offset, "empty delimiter"));
+            }
+            escapes.push(Escape { open, close });
+        }
+        Ok(escapes)
+    }
+
+    fn tag(&mut self, classes: &BTreeMap<String, String>) -> Result<Match, GrammarError> {
+        let offset = self.offset;
+        let tag = self.byte()?;
+
+        let scope = match tag >> SCOPE_SHIFT {
+            PUNCTUATION => Scope::Punctuation,
+            VOWEL => Scope::Vowel,
+            CONSONANT => Scope::Consonant,
+            NUMBER => Scope::Number,
+            WHITESPACE => Scope::Whitespace,
+            START => Scope::Start,
+            END => Scope::End,
+            CLASS => {
+                let name = self.string()?;
+                if !classes.contains_key(&name) {
+                    return Err(self.error(offset, "unknown class"));
+                }
+                Scope::Class(name)
+            }
+            EXACT => Scope::Exact(self.string()?),
+            EXACT_ANY => Scope::ExactAny(self.strings()?),
+            _ => return Err(self.error(offset, "unknown scope")),
+        };
+        let kind = if tag & SUFFIX != 0 {
+            MatchType::Suffix
+        } else {
+            MatchType::Prefix
+        };
+
+        Ok(Match {
+            kind,
+            scope,
+            negative: tag & NEGATIVE != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::{Grammar, GrammarError};
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_embedded_grammar_is_up_to_date() {
+        // Regenerate with `cargo run --example compile_grammar src/AvroPhonetic.json src/AvroPhonetic.bin`
+        let json = Grammar::from_json_str(include_str!("AvroPhonetic.json")).unwrap();
+        assert_eq!(json.to_bytes().as_slice(), &include_bytes!("AvroPhonetic.bin")[..]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let grammar = Grammar::from_bytes(include_bytes!("AvroPhonetic.bin")).unwrap();
+        assert_eq!(grammar.patterns.len(), 289);
+        assert_eq!(Grammar::from_bytes(&grammar.to_bytes()).unwrap(), grammar);
+
+        let extended = crate::parser::extended(grammar);
+        assert_eq!(Grammar::from_bytes(&extended.to_bytes()).unwrap(), extended);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = include_bytes!("AvroPhonetic.bin");
+
+        let err = Grammar::from_bytes(b"{ \"patterns\": [] }").unwrap_err();
+        assert_eq!(err.to_string(), "invalid binary grammar at byte 0: not a grammar");
+
+        let err = Grammar::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(matches!(err, GrammarError::InvalidBinary { reason: "unexpected end", .. }));
+
+        let mut trailing = bytes.to_vec();
+        trailing.push(0);
+        let err = Grammar::from_bytes(&trailing).unwrap_err();
+        assert!(matches!(err, GrammarError::InvalidBinary { reason: "trailing bytes", .. }));
+
+        let mut version = bytes.to_vec();
+        version[4] = 0xff;
+        let err = Grammar::from_bytes(&version).unwrap_err();
+        assert_eq!(err.to_string(), "invalid binary grammar at byte 4: unsupported version");
+    }
+}
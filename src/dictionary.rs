@@ -0,0 +1,182 @@
+//! Dictionary based word suggestions.
+//!
+//! The phonetic conversion only produces the most likely spelling of the
+//! input, e.g. `sorir` converts into সরির, while the correct spelling is
+//! শরীর. A [`Dictionary`](struct.Dictionary.html) holds a list of correctly
+//! spelled Bengali words and looks up the words which the input can produce
+//! when the `alternatives` of the grammar are taken into account.
+//!
+//! # Example
+//! ```rust
+//! use rupantor::avro::AvroPhonetic;
+//! use rupantor::dictionary::Dictionary;
+//!
+//! let dictionary = Dictionary::from_word_list("শরীর\nসাপ\nশাপ\n");
+//! let suggestions = AvroPhonetic::new().suggest("sorir", &dictionary, 5);
+//! assert_eq!(suggestions.conversion, "সরির");
+//! assert_eq!(suggestions.words, vec!["শরীর"]);
+//! ```
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::ops::Bound;
+use std::path::Path;
+use crate::parser::{PhoneticParser, Step};
+
+/// A list of Bengali words, the more common ones first.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    /// Every word and its position in the list.
+    words: BTreeMap<String, usize>,
+}
+
+/// The result of a dictionary lookup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestions {
+    /// The plain phonetic conversion of the input.
+    pub conversion: String,
+    /// The matching dictionary words, the best match first.
+    pub words: Vec<String>,
+}
+
+impl Dictionary {
+    /// Creates an empty dictionary.
+    pub fn new() -> Dictionary {
+        Dictionary::default()
+    }
+
+    /// Creates a dictionary from a word list with one word per line.
+    /// Empty lines are ignored.
+    pub fn from_word_list(text: &str) -> Dictionary {
+        let mut dictionary = Dictionary::new();
+        for line in text.lines() {
+            dictionary.insert(line);
+        }
+        dictionary
+    }
+
+    /// Loads a word list file, see [`from_word_list`](#method.from_word_list).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Dictionary> {
+        fs::read_to_string(path).map(|text| Dictionary::from_word_list(&text))
+    }
+
+    /// Adds a word after all of the words already in the dictionary.
+    /// Surrounding whitespace is removed.
+    pub fn insert(&mut self, word: &str) {
+        let word = word.trim();
+        if !word.is_empty() && !self.words.contains_key(word) {
+            let position = self.words.len();
+            self.words.insert(word.to_string(), position);
+        }
+    }
+
+    /// Returns `true` if the dictionary contains the word.
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains_key(word)
+    }
+
+    /// Returns the number of words.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns `true` if the dictionary has no words.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Returns at most `limit` dictionary words which the given parser can
+    /// produce from `input`, if the `alternatives` of the applied patterns
+    /// and rules are also considered.
+    ///
+    /// Words needing fewer and more preferred alternatives come first,
+    /// then the words which come first in the dictionary.
+    pub fn lookup(&self, parser: &PhoneticParser, input: &str, limit: usize) -> Vec<String> {
+        let fixed = parser.fix_string(input);
+        let mut steps = Vec::new();
+        let mut cur = 0;
+        while cur < fixed.len() {
+            let step = parser.step(input, &fixed, cur);
+            cur = step.end;
+            steps.push(step);
+        }
+
+        let mut found = Vec::new();
+        self.search(&steps, &mut String::new(), 0, &mut found);
+        found.sort();
+
+        let mut seen = HashSet::new();
+        found
+            .into_iter()
+            .filter(|&(_, _, word)| seen.insert(word))
+            .take(limit)
+            .map(|(_, _, word)| word.to_string())
+            .collect()
+    }
+
+    /// Collects the words which start with `prefix` and continue with
+    /// the output of `steps`, along with their cost and position.
+    fn search<'a>(&'a self, steps: &[Step], prefix: &mut String, cost: usize, found: &mut Vec<(usize, usize, &'a str)>) {
+        let (step, rest) = match steps.split_first() {
+            Some(split) => split,
+            None => {
+                if let Some((word, &position)) = self.words.get_key_value(prefix.as_str()) {
+                    found.push((cost, position, word));
+                }
+                return;
+            }
+        };
+
+        let len = prefix.len();
+        let outputs = Some(step.output.as_ref()).into_iter().chain(step.alternatives.iter().map(String::as_str));
+        for (index, output) in outputs.enumerate() {
+            prefix.push_str(output);
+            if self.has_prefix(prefix) {
+                self.search(rest, prefix, cost + index, found);
+            }
+            prefix.truncate(len);
+        }
+    }
+
+    /// Checks whether any word starts with `prefix`.
+    fn has_prefix(&self, prefix: &str) -> bool {
+        self.words
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .next()
+            .is_some_and(|(word, _)| word.starts_with(prefix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avro::AvroPhonetic;
+    use super::*;
+
+    #[test]
+    fn test_word_list() {
+        let mut dictionary = Dictionary::from_word_list("সাপ\n\n  শাপ \r\nসাপ\n");
+        assert_eq!(dictionary.len(), 2);
+        assert!(dictionary.contains("শাপ"));
+
+        dictionary.insert("ষাপ");
+        dictionary.insert(" ");
+        assert_eq!(dictionary.len(), 3);
+        assert!(Dictionary::new().is_empty());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let avro = AvroPhonetic::new();
+        let dictionary = Dictionary::from_word_list("শিশু\nশাপ\nসাপ\nশরীর\nবাংলাদেশ\nআমি");
+
+        assert_eq!(avro.suggest("sap", &dictionary, 5).words, vec!["সাপ", "শাপ"]);
+        assert_eq!(avro.suggest("sap", &dictionary, 1).words, vec!["সাপ"]);
+        assert_eq!(avro.suggest("shishu", &dictionary, 5).words, vec!["শিশু"]);
+        assert_eq!(avro.suggest("bangladesh", &dictionary, 5).words, vec!["বাংলাদেশ"]);
+        assert_eq!(avro.suggest("AmI", &dictionary, 5).words, vec!["আমি"]);
+        assert_eq!(avro.suggest("tumi", &dictionary, 5), Suggestions {
+            conversion: avro.convert("tumi"),
+            words: Vec::new(),
+        });
+    }
+}
@@ -0,0 +1,609 @@
+//! A compact text format for grammars.
+//!
+//! Every line of the text is a setting, a pattern or a comment starting
+//! with `#`. A pattern is written as `find -> replace`, followed by its
+//! rules, each starting with `|`. A rule lists its conditions before its own
+//! `->`. Rules may continue on the following lines:
+//!
+//! ```text
+//! vowel = aeiou
+//! consonant = bcdfghjklmnpqrstvwxyz
+//! number = 0123456789
+//! casesensitive = oO
+//! class.aspirate = h
+//!
+//! k -> ক
+//! o -> অ | prefix:consonant -> ""
+//! i -> ি
+//!     | prefix:!consonant -> ই
+//!     | prefix:"`" suffix:end -> ই
+//! s -> স / শ / ষ
+//! ```
+//!
+//! A condition is `prefix:` or `suffix:` followed by a scope, optionally
+//! negated with `!`. Scopes are the built-in scopes like `vowel` or `start`,
+//! the names of classes, a quoted exact value like `"kh"` or a list of exact
+//! values like `["k", "g"]`. Replacements may be followed by alternatives
+//! separated by `/`.
+//!
+//! Texts are written as they are, unless they are empty or contain spaces or
+//! one of `"!:|/=[],#`, in which case they are quoted. Quoted texts support
+//! the escapes `\"`, `\\`, `\n`, `\t` and `\u{200d}`.
+//!
+//! The settings are `vowel`, `consonant`, `number` and `casesensitive`, which
+//! are required, `punctuation`, `numberboundary` (`true` or `false`),
+//! `class.<name>` for every class, `verbatim = <open> <close>` for every
+//! verbatim escape and `codepoint = <open> <close>`.
+//!
+//! # Example
+//! ```rust
+//! use rupantor::grammar::Grammar;
+//! use rupantor::parser::PhoneticParser;
+//!
+//! let grammar = Grammar::from_dsl_str(r#"
+//! vowel = aeiou
+//! consonant = bcdfghjklmnpqrstvwxyz
+//! number = 0123456789
+//! casesensitive = ""
+//!
+//! k -> ক
+//! o -> অ | prefix:consonant -> ""
+//! "#).unwrap();
+//! assert_eq!(grammar.patterns[1].rules[0].replace, "");
+//! assert_eq!(PhoneticParser::from_grammar(grammar).convert("oko"), "অক");
+//!
+//! let err = Grammar::from_dsl_str("vowel = aeiou\nk -> ক | prefix:vowl -> x").unwrap_err();
+//! assert_eq!(err.to_string(), "line 2, column 17: unknown scope `vowl`");
+//! ```
+use std::collections::BTreeMap;
+use crate::grammar::{Escape, Grammar, GrammarError, Match, MatchType, Pattern, Rule, Scope, BUILTIN_SCOPES};
+
+/// The characters which end an unquoted text.
+const RESERVED: &str = "\"!:|/=[],#";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Arrow,
+    Bar,
+    Slash,
+    Bang,
+    Colon,
+    Equals,
+    Open,
+    Close,
+    Comma,
+    Text(String),
+    Quoted(String),
+}
+
+/// The tokens of a line, along with the column where each of them starts.
+struct Line {
+    number: usize,
+    tokens: Vec<(usize, Token)>,
+    /// The column after the last character.
+    end: usize,
+    /// The next token.
+    pos: usize,
+}
+
+impl Grammar {
+    /// Parses a `Grammar` from the text format described in the
+    /// [`dsl`](../dsl/index.html) module.
+    pub fn from_dsl_str(text: &str) -> Result<Grammar, GrammarError> {
+        let mut settings = BTreeMap::new();
+        let mut classes = BTreeMap::new();
+        let mut verbatim = Vec::new();
+        let mut code_point = None;
+        let mut number_boundary = None;
+        let mut patterns: Vec<Pattern> = Vec::new();
+        // Class scopes used by rules, with their position.
+        let mut used = Vec::new();
+        let mut continues = false;
+
+        for (index, text) in text.lines().enumerate() {
+            let mut line = Line::tokenize(index + 1, text)?;
+            match line.tokens.as_slice() {
+                [] => continue,
+                [(column, Token::Bar), ..] => {
+                    let pattern = patterns
+                        .last_mut()
+                        .filter(|_| continues)
+                        .ok_or_else(|| line.error(*column, "rule outside of a pattern"))?;
+                    line.rules(&mut pattern.rules, &mut used)?;
+                }
+                [(column, Token::Text(key)), (_, Token::Equals), ..] => {
+                    let (column, key) = (*column, key.clone());
+                    line.pos = 2;
+                    let duplicate = || line.error(column, format!("duplicate setting `{}`", key));
+                    match key.as_str() {
+                        "vowel" | "consonant" | "number" | "casesensitive" | "punctuation" => {
+                            if settings.contains_key(&key) {
+                                return Err(duplicate());
+                            }
+                            let value = line.text("a text")?;
+                            settings.insert(key, value);
+                        }
+                        "numberboundary" => {
+                            if number_boundary.is_some() {
+                                return Err(duplicate());
+                            }
+                            let column = line.column();
+                            number_boundary = match line.next() {
+                                Some(Token::Text(value)) if value == "true" => Some(true),
+                                Some(Token::Text(value)) if value == "false" => Some(false),
+                                _ => return Err(line.error(column, "expected `true` or `false`")),
+                            };
+                        }
+                        "verbatim" => verbatim.push(line.escape()?),
+                        "codepoint" => {
+                            if code_point.is_some() {
+                                return Err(duplicate());
+                            }
+                            code_point = Some(line.escape()?);
+                        }
+                        _ => {
+                            let name = key
+                                .strip_prefix("class.")
+                                .filter(|name| !name.is_empty())
+                                .ok_or_else(|| line.error(column, format!("unknown setting `{}`", key)))?;
+                            if BUILTIN_SCOPES.contains(&name) {
+                                return Err(line.error(column + 6, format!("`{}` is the name of a built-in scope", name)));
+                            }
+                            if classes.contains_key(name) {
+                                return Err(duplicate());
+                            }
+                            classes.insert(name.to_string(), line.text("a text")?);
+                        }
+                    }
+                    line.finish("the end of the line")?;
+                    continues = false;
+                }
+                _ => {
+                    let column = line.column();
+                    let find = line.text("a pattern or a setting")?;
+                    if find.is_empty() {
+                        return Err(line.error(column, "the pattern must not be empty"));
+                    }
+                    line.expect(Token::Arrow, "`->`")?;
+                    let replace = line.text("a replacement")?;
+                    let alternatives = line.alternatives()?;
+                    let mut rules = Vec::new();
+                    line.rules(&mut rules, &mut used)?;
+                    patterns.push(Pattern { find, replace, alternatives, rules });
+                    continues = true;
+                }
+            }
+        }
+
+        for (name, line, column) in used {
+            if !classes.contains_key(&name) {
+                return Err(GrammarError::Dsl { line, column, reason: format!("unknown scope `{}`", name) });
+            }
+        }
+
+        let mut setting = |key: &str| settings.remove(key).ok_or_else(|| GrammarError::MissingField { path: key.to_string() });
+        Ok(Grammar {
+            patterns,
+            vowel: setting("vowel")?,
+            consonant: setting("consonant")?,
+            number: setting("number")?,
+            case_sensitive: setting("casesensitive")?,
+            punctuation: settings.remove("punctuation"),
+            number_boundary: number_boundary.unwrap_or(true),
+            classes,
+            verbatim,
+            code_point,
+        })
+    }
+
+    /// Writes the grammar in the text format described in the
+    /// [`dsl`](../dsl/index.html) module.
+    pub fn to_dsl(&self) -> String {
+        let mut dsl = String::new();
+        dsl += &format!("vowel = {}\n", text(&self.vowel));
+        dsl += &format!("consonant = {}\n", text(&self.consonant));
+        dsl += &format!("number = {}\n", text(&self.number));
+        dsl += &format!("casesensitive = {}\n", text(&self.case_sensitive));
+        if let Some(punctuation) = &self.punctuation {
+            dsl += &format!("punctuation = {}\n", text(punctuation));
+        }
+        if !self.number_boundary {
+            dsl += "numberboundary = false\n";
+        }
+        for (name, characters) in &self.classes {
+            dsl += &format!("class.{} = {}\n", name, text(characters));
+        }
+        for escape in &self.verbatim {
+            dsl += &format!("verbatim = {} {}\n", text(&escape.open), text(&escape.close));
+        }
+        if let Some(escape) = &self.code_point {
+            dsl += &format!("codepoint = {} {}\n", text(&escape.open), text(&escape.close));
+        }
+
+        for pattern in &self.patterns {
+            dsl += &format!("\n{} -> {}", text(&pattern.find), replacement(&pattern.replace, &pattern.alternatives));
+            for rule in &pattern.rules {
+                dsl += "\n    |";
+                for _match in &rule.matches {
+                    dsl += &format!(" {}", condition(_match));
+                }
+                dsl += &format!(" -> {}", replacement(&rule.replace, &rule.alternatives));
+            }
+        }
+        dsl.push('\n');
+        dsl
+    }
+}
+
+impl Line {
+    fn tokenize(number: usize, line: &str) -> Result<Line, GrammarError> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut tokens = Vec::new();
+        let error = |column, reason: &str| GrammarError::Dsl { line: number, column, reason: reason.to_string() };
+
+        let mut i = 0;
+        while i < chars.len() {
+            let column = i + 1;
+            let token = match chars[i] {
+                '#' => break,
+                c if c.is_whitespace() => {
+                    i += 1;
+                    continue;
+                }
+                '-' if chars.get(i + 1) == Some(&'>') => {
+                    i += 1;
+                    Token::Arrow
+                }
+                '|' => Token::Bar,
+                '/' => Token::Slash,
+                '!' => Token::Bang,
+                ':' => Token::Colon,
+                '=' => Token::Equals,
+                '[' => Token::Open,
+                ']' => Token::Close,
+                ',' => Token::Comma,
+                '"' => {
+                    let mut quoted = String::new();
+                    loop {
+                        i += 1;
+                        match chars.get(i) {
+                            None => return Err(error(column, "unterminated text")),
+                            Some('"') => break,
+                            Some('\\') => {
+                                let escape = i + 1;
+                                i += 1;
+                                match chars.get(i) {
+                                    Some('"') => quoted.push('"'),
+                                    Some('\\') => quoted.push('\\'),
+                                    Some('n') => quoted.push('\n'),
+                                    Some('t') => quoted.push('\t'),
+                                    Some('u') if chars.get(i + 1) == Some(&'{') => {
+                                        let digits: String = chars[i + 2..].iter().take_while(|&&c| c != '}').collect();
+                                        // `from_str_radix` would also accept a sign.
+                                        let valid = (1..=6).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_hexdigit());
+                                        let character = Some(&digits)
+                                            .filter(|_| valid)
+                                            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                                            .and_then(char::from_u32)
+                                            .filter(|_| chars.len() > i + 2 + digits.chars().count())
+                                            .ok_or_else(|| error(escape, "invalid code point"))?;
+                                        quoted.push(character);
+                                        i += 2 + digits.chars().count();
+                                    }
+                                    _ => return Err(error(escape, "invalid escape")),
+                                }
+                            }
+                            Some(&c) => quoted.push(c),
+                        }
+                    }
+                    Token::Quoted(quoted)
+                }
+                _ => {
+                    let start = i;
+                    while i + 1 < chars.len() && is_plain(chars[i + 1]) && !(chars[i + 1] == '-' && chars.get(i + 2) == Some(&'>')) {
+                        i += 1;
+                    }
+                    Token::Text(chars[start..=i].iter().collect())
+                }
+            };
+            tokens.push((column, token));
+            i += 1;
+        }
+
+        Ok(Line { number, tokens, end: chars.len() + 1, pos: 0 })
+    }
+
+    fn error<S: Into<String>>(&self, column: usize, reason: S) -> GrammarError {
+        GrammarError::Dsl { line: self.number, column, reason: reason.into() }
+    }
+
+    /// The column of the next token.
+    fn column(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |&(column, _)| column)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, token)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, name: &str) -> Result<(), GrammarError> {
+        if self.peek() == Some(&expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(self.column(), format!("expected {}", name)))
+        }
+    }
+
+    /// Checks that no tokens are left.
+    fn finish(&self, expected: &str) -> Result<(), GrammarError> {
+        match self.peek() {
+            Some(_) => Err(self.error(self.column(), format!("expected {}", expected))),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads a plain or a quoted text.
+    fn text(&mut self, expected: &str) -> Result<String, GrammarError> {
+        let column = self.column();
+        match self.next() {
+            Some(Token::Text(text)) | Some(Token::Quoted(text)) => Ok(text),
+            _ => Err(self.error(column, format!("expected {}", expected))),
+        }
+    }
+
+    /// Reads the alternatives following a replacement.
+    fn alternatives(&mut self) -> Result<Vec<String>, GrammarError> {
+        let mut alternatives = Vec::new();
+        while self.peek() == Some(&Token::Slash) {
+            self.pos += 1;
+            alternatives.push(self.text("an alternative")?);
+        }
+        Ok(alternatives)
+    }
+
+    fn escape(&mut self) -> Result<Escape, GrammarError> {
+        let mut delimiter = |expected| {
+            let column = self.column();
+            match self.text(expected)? {
+                delimiter if delimiter.is_empty() => Err(self.error(column, "the delimiter must not be empty")),
+                delimiter => Ok(delimiter),
+            }
+        };
+        Ok(Escape {
+            open: delimiter("an opening delimiter")?,
+            close: delimiter("a closing delimiter")?,
+        })
+    }
+
+    /// Reads the rules up to the end of the line.
+    fn rules(&mut self, rules: &mut Vec<Rule>, used: &mut Vec<(String, usize, usize)>) -> Result<(), GrammarError> {
+        while self.peek().is_some() {
+            self.expect(Token::Bar, "`|`")?;
+            let mut matches = Vec::new();
+            while self.peek() != Some(&Token::Arrow) {
+                matches.push(self.condition(used)?);
+            }
+            self.pos += 1;
+            let replace = self.text("a replacement")?;
+            rules.push(Rule {
+                matches,
+                replace,
+                alternatives: self.alternatives()?,
+            });
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self, used: &mut Vec<(String, usize, usize)>) -> Result<Match, GrammarError> {
+        let start = self.column();
+        let kind = match self.next() {
+            Some(Token::Text(kind)) if kind == "prefix" => MatchType::Prefix,
+            Some(Token::Text(kind)) if kind == "suffix" => MatchType::Suffix,
+            _ => return Err(self.error(start, "expected `prefix`, `suffix` or `->`")),
+        };
+        self.expect(Token::Colon, "`:`")?;
+        let negative = self.peek() == Some(&Token::Bang);
+        if negative {
+            self.pos += 1;
+        }
+
+        let column = self.column();
+        let scope = match self.next() {
+            Some(Token::Text(scope)) => match scope.as_str() {
+                "punctuation" => Scope::Punctuation,
+                "vowel" => Scope::Vowel,
+                "consonant" => Scope::Consonant,
+                "number" => Scope::Number,
+                "whitespace" => Scope::Whitespace,
+                "start" => Scope::Start,
+                "end" => Scope::End,
+                _ => {
+                    used.push((scope.clone(), self.number, column));
+                    Scope::Class(scope)
+                }
+            },
+            Some(Token::Quoted(value)) => Scope::Exact(value),
+            Some(Token::Open) => {
+                let mut values = Vec::new();
+                loop {
+                    let column = self.column();
+                    match self.next() {
+                        Some(Token::Quoted(value)) => values.push(value),
+                        _ => return Err(self.error(column, "expected a quoted text")),
+                    }
+                    let column = self.column();
+                    match self.next() {
+                        Some(Token::Comma) => continue,
+                        Some(Token::Close) => break,
+                        _ => return Err(self.error(column, "expected `,` or `]`")),
+                    }
+                }
+                Scope::ExactAny(values)
+            }
+            _ => return Err(self.error(column, "expected a scope")),
+        };
+
+        let _match = Match { kind, scope, negative };
+        if let Some((scope, expected)) = _match.misplaced() {
+            return Err(self.error(start, format!("`{}` can only be a {}", scope, expected)));
+        }
+        Ok(_match)
+    }
+}
+
+/// Checks whether a character may be part of an unquoted text.
+fn is_plain(c: char) -> bool {
+    !c.is_whitespace() && !RESERVED.contains(c)
+}
+
+/// Checks for characters which are not visible in a text editor.
+fn is_invisible(c: char) -> bool {
+    c.is_control() || matches!(c, '\u{200b}'..='\u{200f}' | '\u{2028}'..='\u{202e}' | '\u{2060}'..='\u{2064}' | '\u{feff}')
+}
+
+/// Writes a text, quoting it if necessary.
+fn text(text: &str) -> String {
+    if !text.is_empty() && !text.contains("->") && text.chars().all(|c| is_plain(c) && !is_invisible(c)) {
+        text.to_string()
+    } else {
+        quote(text)
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted += "\\n",
+            '\t' => quoted += "\\t",
+            c if is_invisible(c) || (c.is_whitespace() && c != ' ') => quoted += &format!("\\u{{{:x}}}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn replacement(replace: &str, alternatives: &[String]) -> String {
+    let mut replacement = text(replace);
+    for alternative in alternatives {
+        replacement += &format!(" / {}", text(alternative));
+    }
+    replacement
+}
+
+fn condition(_match: &Match) -> String {
+    let kind = match _match.kind {
+        MatchType::Prefix => "prefix",
+        MatchType::Suffix => "suffix",
+    };
+    let scope = match &_match.scope {
+        Scope::Punctuation => "punctuation".to_string(),
+        Scope::Vowel => "vowel".to_string(),
+        Scope::Consonant => "consonant".to_string(),
+        Scope::Number => "number".to_string(),
+        Scope::Whitespace => "whitespace".to_string(),
+        Scope::Start => "start".to_string(),
+        Scope::End => "end".to_string(),
+        Scope::Class(name) => name.clone(),
+        Scope::Exact(value) => quote(value),
+        Scope::ExactAny(values) => {
+            format!("[{}]", values.iter().map(|value| quote(value)).collect::<Vec<_>>().join(", "))
+        }
+    };
+    format!("{}:{}{}", kind, if _match.negative { "!" } else { "" }, scope)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avro::AvroPhonetic;
+    use super::*;
+
+    const HEADER: &str = "vowel = aeiou\nconsonant = bcdfghjklmnpqrstvwxyz\nnumber = 0123456789\ncasesensitive = \"\"\n";
+
+    #[test]
+    fn test_from_dsl_str() {
+        let grammar = Grammar::from_dsl_str(&format!(r#"{}
+class.aspirate = h  # aspirated consonants
+verbatim = {{ }}
+
+"a b" -> "\u{{200d}}\"" / x
+k -> ক
+    | suffix:!aspirate prefix:["k", "g"] -> "" / ক্ক
+    # a comment between rules
+    | prefix:start -> ক | -> খ
+"#, HEADER)).unwrap();
+
+        assert_eq!(grammar.classes["aspirate"], "h");
+        assert_eq!(grammar.verbatim, vec![Escape { open: "{".to_string(), close: "}".to_string() }]);
+        assert!(grammar.number_boundary);
+        assert_eq!(grammar.patterns[0], Pattern {
+            find: "a b".to_string(),
+            replace: "\u{200d}\"".to_string(),
+            alternatives: vec!["x".to_string()],
+            rules: Vec::new(),
+        });
+
+        let rules = &grammar.patterns[1].rules;
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].matches, vec![
+            Match { kind: MatchType::Suffix, scope: Scope::Class("aspirate".to_string()), negative: true },
+            Match { kind: MatchType::Prefix, scope: Scope::ExactAny(vec!["k".to_string(), "g".to_string()]), negative: false },
+        ]);
+        assert_eq!(rules[0].alternatives, vec!["ক্ক"]);
+        assert_eq!(rules[2].matches, Vec::new());
+        assert_eq!(rules[2].replace, "খ");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let avro = AvroPhonetic::new().grammar();
+        let dsl = avro.to_dsl();
+        assert!(dsl.contains("\no -> \"\"\n    | prefix:vowel prefix:!\"o\" -> ও\n"));
+        assert_eq!(Grammar::from_dsl_str(&dsl).unwrap(), avro);
+        #[cfg(feature = "json")]
+        crate::parser::tests::assert_avro(&crate::parser::PhoneticParser::from_grammar(Grammar::from_dsl_str(&dsl).unwrap()));
+
+        let extended = crate::parser::extended(avro);
+        assert_eq!(Grammar::from_dsl_str(&extended.to_dsl()).unwrap(), extended);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |text: &str| Grammar::from_dsl_str(&format!("{}{}", HEADER, text)).unwrap_err().to_string();
+
+        assert_eq!(error("k ক"), "line 5, column 3: expected `->`");
+        assert_eq!(error("k -> ক | prefix vowel -> x"), "line 5, column 17: expected `:`");
+        assert_eq!(error("k -> ক | vowel -> x"), "line 5, column 10: expected `prefix`, `suffix` or `->`");
+        assert_eq!(error("k -> ক | prefix:[\"a\" \"b\"] -> x"), "line 5, column 22: expected `,` or `]`");
+        assert_eq!(error("k -> ক | prefix:exact -> x"), "line 5, column 17: unknown scope `exact`");
+        assert_eq!(error("k -> ক | suffix:!start -> x"), "line 5, column 10: `start` can only be a prefix");
+        assert_eq!(error("k -> \"ক"), "line 5, column 6: unterminated text");
+        assert_eq!(error("k -> \"\\q\""), "line 5, column 7: invalid escape");
+        assert_eq!(error("k -> \"\\u{d800}\""), "line 5, column 7: invalid code point");
+        assert_eq!(error("k -> \"\\u{+41}\""), "line 5, column 7: invalid code point");
+        assert_eq!(error("k -> \"\\u{0000041}\""), "line 5, column 7: invalid code point");
+        assert_eq!(error("k -> ক x"), "line 5, column 8: expected `|`");
+        assert_eq!(error("\"\" -> ক"), "line 5, column 1: the pattern must not be empty");
+        assert_eq!(error("| -> ক"), "line 5, column 1: rule outside of a pattern");
+        assert_eq!(error("k -> ক\nvowel = a"), "line 6, column 1: duplicate setting `vowel`");
+        assert_eq!(error("k -> ক\nclass.start = a"), "line 6, column 7: `start` is the name of a built-in scope");
+        assert_eq!(error("numberboundary = yes"), "line 5, column 18: expected `true` or `false`");
+        assert_eq!(error("codepoint = \"\" }"), "line 5, column 13: the delimiter must not be empty");
+        assert_eq!(error("vowels = a"), "line 5, column 1: unknown setting `vowels`");
+
+        let err = Grammar::from_dsl_str("vowel = a\nconsonant = b\nnumber = 1").unwrap_err();
+        assert_eq!(err.to_string(), "casesensitive: missing field");
+    }
+}
@@ -0,0 +1,172 @@
+//! Grammar files in Json, TOML and YAML.
+//!
+//! Besides Json, grammars can be written in TOML with the `toml` feature and
+//! in YAML with the `yaml` feature. All formats describe the same structure
+//! with the same keys, so a grammar can be converted between them without
+//! changing its meaning. [`Grammar::load`](../grammar/struct.Grammar.html#method.load)
+//! picks the format of every file from its extension.
+//!
+//! # Example
+//! ```rust
+//! # #[cfg(feature = "yaml")] {
+//! use rupantor::format::{self, Format};
+//! use rupantor::grammar::Grammar;
+//!
+//! let json = r#"{
+//!     "patterns": [{ "find": "k", "replace": "ক", "rules": [] }],
+//!     "vowel": "aeiou",
+//!     "consonant": "bcdfghjklmnpqrstvwxyz",
+//!     "number": "0123456789",
+//!     "casesensitive": ""
+//! }"#;
+//! let yaml = format::convert(json, Format::Json, Format::Yaml).unwrap();
+//! assert!(yaml.contains("vowel: aeiou"));
+//! assert_eq!(Grammar::from_yaml_str(&yaml).unwrap(), Grammar::from_json_str(json).unwrap());
+//! # }
+//! ```
+use std::path::Path;
+use serde_json::Value;
+use crate::grammar::{Grammar, GrammarError};
+
+/// A grammar file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl Format {
+    /// Returns the format of a file from its extension: `json`, `toml`,
+    /// `yaml` or `yml`. Returns `None` if the extension is not known or
+    /// the feature of its format is disabled.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        match path.as_ref().extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            #[cfg(feature = "toml")]
+            "toml" => Some(Format::Toml),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Returns the cargo feature which is needed for the format of a
+    /// file, if the extension is known but its feature is disabled.
+    pub(crate) fn disabled_feature<P: AsRef<Path>>(path: P) -> Option<&'static str> {
+        match path.as_ref().extension()?.to_str()? {
+            #[cfg(not(feature = "toml"))]
+            "toml" => Some("toml"),
+            #[cfg(not(feature = "yaml"))]
+            "yaml" | "yml" => Some("yaml"),
+            _ => None,
+        }
+    }
+
+    /// Parses a text in this format into the equivalent Json value.
+    pub(crate) fn parse(self, text: &str) -> Result<Value, GrammarError> {
+        match self {
+            Format::Json => serde_json::from_str(text).map_err(GrammarError::Syntax),
+            #[cfg(feature = "toml")]
+            Format::Toml => toml::from_str(text).map_err(GrammarError::Toml),
+            #[cfg(feature = "yaml")]
+            Format::Yaml => serde_yaml_ng::from_str(text).map_err(GrammarError::Yaml),
+        }
+    }
+
+    /// Writes a Json value in this format.
+    pub(crate) fn write(self, value: &Value) -> Result<String, GrammarError> {
+        match self {
+            Format::Json => Ok(crate::json::canonical(value)),
+            #[cfg(feature = "toml")]
+            Format::Toml => toml::to_string_pretty(value).map_err(GrammarError::TomlWrite),
+            #[cfg(feature = "yaml")]
+            Format::Yaml => serde_yaml_ng::to_string(value).map_err(GrammarError::Yaml),
+        }
+    }
+}
+
+/// Rewrites a grammar from one format into another.
+///
+/// The grammar is not validated, so the parts of a grammar which is
+/// split into several files with includes can be converted as well.
+pub fn convert(text: &str, from: Format, to: Format) -> Result<String, GrammarError> {
+    to.write(&from.parse(text)?)
+}
+
+impl Grammar {
+    /// Parses a `Grammar` from the given TOML text.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(toml: &str) -> Result<Grammar, GrammarError> {
+        Grammar::from_value(&Format::Toml.parse(toml)?)
+    }
+
+    /// Parses a `Grammar` from the given YAML text.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_str(yaml: &str) -> Result<Grammar, GrammarError> {
+        Grammar::from_value(&Format::Yaml.parse(yaml)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "toml", feature = "yaml"))]
+    use crate::parser::PhoneticParser;
+    use super::*;
+
+    #[cfg(any(feature = "toml", feature = "yaml"))]
+    const AVRO: &str = include_str!("AvroPhonetic.json");
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(Format::from_path("grammars/avro.json"), Some(Format::Json));
+        assert_eq!(Format::from_path("avro.txt"), None);
+        assert_eq!(Format::from_path("avro"), None);
+        #[cfg(feature = "yaml")]
+        assert_eq!(Format::from_path("avro.yml"), Some(Format::Yaml));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml() {
+        let toml = convert(AVRO, Format::Json, Format::Toml).unwrap();
+        let grammar = Grammar::from_toml_str(&toml).unwrap();
+        assert_eq!(grammar, Grammar::from_json_str(AVRO).unwrap());
+        crate::parser::tests::assert_avro(&PhoneticParser::from_grammar(grammar));
+
+        let json = convert(&toml, Format::Toml, Format::Json).unwrap();
+        assert_eq!(Format::Json.parse(&json).unwrap(), Format::Json.parse(AVRO).unwrap());
+
+        let err = Grammar::from_toml_str("vowel = ").unwrap_err();
+        assert!(matches!(err, GrammarError::Toml(_)));
+        let err = Grammar::from_toml_str("patterns = []").unwrap_err();
+        assert_eq!(err.to_string(), "vowel: missing field");
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml() {
+        let yaml = convert(AVRO, Format::Json, Format::Yaml).unwrap();
+        let grammar = Grammar::from_yaml_str(&yaml).unwrap();
+        assert_eq!(grammar, Grammar::from_json_str(AVRO).unwrap());
+        crate::parser::tests::assert_avro(&PhoneticParser::from_grammar(grammar));
+
+        let json = convert(&yaml, Format::Yaml, Format::Json).unwrap();
+        assert_eq!(Format::Json.parse(&json).unwrap(), Format::Json.parse(AVRO).unwrap());
+
+        let err = Grammar::from_yaml_str("vowel: [").unwrap_err();
+        assert!(matches!(err, GrammarError::Yaml(_)));
+        let err = Grammar::from_yaml_str("patterns:\n  - find: k\n    replace: 1\n    rules: []\n").unwrap_err();
+        assert_eq!(err.to_string(), "patterns[0].replace: expected a string");
+    }
+
+    #[cfg(all(feature = "toml", feature = "yaml"))]
+    #[test]
+    fn test_toml_to_yaml() {
+        let toml = convert(AVRO, Format::Json, Format::Toml).unwrap();
+        let yaml = convert(&toml, Format::Toml, Format::Yaml).unwrap();
+        assert_eq!(Grammar::from_yaml_str(&yaml).unwrap(), Grammar::from_toml_str(&toml).unwrap());
+    }
+}
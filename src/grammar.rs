@@ -0,0 +1,347 @@
+//! Typed representation of a phonetic grammar.
+//!
+//! A grammar is written as Json (see the Avro Phonetic
+//! [grammar file](https://github.com/OpenBangla/rupantor-rs/blob/master/src/AvroPhonetic.json))
+//! and is deserialized into a [`Grammar`](struct.Grammar.html) once, so the
+//! conversion never needs to look into raw Json values. A grammar can also be
+//! stored in a compact binary format, see [`Grammar::to_bytes`](struct.Grammar.html#method.to_bytes),
+//! or built in code with a [`GrammarBuilder`](../builder/struct.GrammarBuilder.html).
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+/// A phonetic grammar: the character classes and the conversion patterns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grammar {
+    /// Conversion patterns.
+    pub patterns: Vec<Pattern>,
+    /// Characters which are considered vowels.
+    pub vowel: String,
+    /// Characters which are considered consonants.
+    pub consonant: String,
+    /// Characters which are considered numbers.
+    pub number: String,
+    /// Characters which are considered punctuation. If `None`, every
+    /// character which is neither a vowel nor a consonant is.
+    pub punctuation: Option<String>,
+    /// Whether numbers are considered punctuation, i.e. word boundaries,
+    /// as far as they are punctuation by `punctuation`. `true` by default.
+    pub number_boundary: bool,
+    /// Named character classes, usable as match scopes.
+    pub classes: BTreeMap<String, String>,
+    /// Characters whose case is significant. Every other character
+    /// is lowercased before conversion.
+    pub case_sensitive: String,
+    /// Delimiters of text which is copied into the output unconverted.
+    pub verbatim: Vec<Escape>,
+    /// Delimiters of a hexadecimal Unicode code point, which is
+    /// inserted into the output.
+    pub code_point: Option<Escape>,
+}
+
+/// The delimiters of an escape in the input, e.g. `{` and `}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escape {
+    pub open: String,
+    pub close: String,
+}
+
+/// A pattern maps the `find` text into the `replace` text,
+/// unless one of its `rules` applies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    pub find: String,
+    pub replace: String,
+    /// Less likely replacements of `find`, offered as conversion candidates.
+    pub alternatives: Vec<String>,
+    pub rules: Vec<Rule>,
+}
+
+/// A rule replaces the matched text with its own `replace` text
+/// when all of its `matches` are satisfied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub matches: Vec<Match>,
+    pub replace: String,
+    /// Less likely replacements, offered as conversion candidates.
+    pub alternatives: Vec<String>,
+}
+
+/// A condition on the text surrounding a matched pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Which side of the matched text is checked.
+    pub kind: MatchType,
+    /// What is checked.
+    pub scope: Scope,
+    /// Whether the condition is negated (`!` prefixed scope).
+    pub negative: bool,
+}
+
+/// The side of the matched text a [`Match`](struct.Match.html) looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchType {
+    /// The text before the match.
+    Prefix,
+    /// The text after the match.
+    Suffix,
+}
+
+/// The kind of text a [`Match`](struct.Match.html) checks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// A punctuation character, or the beginning/end of the input. See
+    /// [`Grammar::punctuation`](struct.Grammar.html#structfield.punctuation).
+    Punctuation,
+    Vowel,
+    Consonant,
+    Number,
+    /// A whitespace character.
+    Whitespace,
+    /// The beginning of the input, only usable as a prefix.
+    Start,
+    /// The end of the input, only usable as a suffix.
+    End,
+    /// A character of the named class of the grammar, compared
+    /// after case normalization.
+    Class(String),
+    /// The exact given text.
+    Exact(String),
+    /// Any of the given texts.
+    ExactAny(Vec<String>),
+}
+
+impl Match {
+    /// Returns the scope and the match type it needs if the match
+    /// looks for the beginning after, or the end before, the matched text.
+    pub(crate) fn misplaced(&self) -> Option<(&'static str, &'static str)> {
+        match (&self.scope, self.kind) {
+            (Scope::Start, MatchType::Suffix) => Some(("start", "prefix")),
+            (Scope::End, MatchType::Prefix) => Some(("end", "suffix")),
+            _ => None,
+        }
+    }
+}
+
+/// Names which can't be used for character classes.
+pub(crate) const BUILTIN_SCOPES: &[&str] = &[
+    "punctuation", "vowel", "consonant", "number", "whitespace", "start", "end", "exact",
+];
+
+impl Grammar {
+    /// Checks that the rules only use classes of the grammar, and `start`
+    /// and `end` only on their side. The path of an error starts with
+    /// `path`, the path of the rules.
+    pub(crate) fn check_rules(&self, rules: &[Rule], path: &str) -> Result<(), GrammarError> {
+        for (i, rule) in rules.iter().enumerate() {
+            for (j, _match) in rule.matches.iter().enumerate() {
+                if let Some((scope, expected)) = _match.misplaced() {
+                    return Err(GrammarError::MisplacedScope {
+                        path: format!("{}[{}].matches[{}].type", path, i, j),
+                        scope,
+                        expected,
+                    });
+                }
+                if let Scope::Class(name) = &_match.scope {
+                    if !self.classes.contains_key(name) {
+                        return Err(GrammarError::UnknownScope {
+                            path: format!("{}[{}].matches[{}].scope", path, i, j),
+                            value: name.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the grammar the way it is checked when it is loaded: class
+    /// names, `find`s, escape delimiters and the scopes used by rules.
+    /// Useful for grammars which are built or changed in code.
+    pub fn validate(&self) -> Result<(), GrammarError> {
+        if let Some(name) = self.classes.keys().find(|name| BUILTIN_SCOPES.contains(&name.as_str())) {
+            return Err(GrammarError::ReservedClass { path: format!("classes.{}", name) });
+        }
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            if pattern.find.is_empty() {
+                return Err(GrammarError::EmptyFind { path: format!("patterns[{}].find", i) });
+            }
+            self.check_rules(&pattern.rules, &format!("patterns[{}].rules", i))?;
+        }
+
+        let escapes = self
+            .verbatim
+            .iter()
+            .enumerate()
+            .map(|(i, escape)| (format!("verbatim[{}]", i), escape))
+            .chain(self.code_point.iter().map(|escape| ("codepoint".to_string(), escape)));
+        for (path, escape) in escapes {
+            for (key, delimiter) in [("open", &escape.open), ("close", &escape.close)] {
+                if delimiter.is_empty() {
+                    return Err(GrammarError::EmptyDelimiter { path: format!("{}.{}", path, key) });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Normalizes the case of a character the way the input is normalized
+    /// before conversion: characters whose case is not significant are
+    /// lowercased.
+    pub(crate) fn fix_char(&self, character: char) -> char {
+        if self.case_sensitive.contains(character.to_ascii_lowercase()) {
+            character
+        } else {
+            character.to_ascii_lowercase()
+        }
+    }
+}
+
+/// Errors which can occur while loading a grammar.
+///
+/// The variants describing an invalid grammar carry the Json path of the
+/// offending value, e.g. `patterns[213].rules[1].matches[0].scope`.
+///
+/// Some variants only exist with the cargo feature they belong to.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum GrammarError {
+    /// The text is not valid Json.
+    #[cfg(feature = "json")]
+    Syntax(serde_json::Error),
+    /// A required field is missing.
+    MissingField { path: String },
+    /// A field has the wrong Json type.
+    InvalidType { path: String, expected: &'static str },
+    /// A pattern has an empty `find`.
+    EmptyFind { path: String },
+    /// An escape has an empty delimiter.
+    EmptyDelimiter { path: String },
+    /// A match `type` is neither `prefix` nor `suffix`.
+    UnknownMatchType { path: String, value: String },
+    /// A match `scope` is not known.
+    UnknownScope { path: String, value: String },
+    /// A `start` match is not a prefix, or an `end` match not a suffix.
+    MisplacedScope { path: String, scope: &'static str, expected: &'static str },
+    /// A character class has the name of a built-in scope.
+    ReservedClass { path: String },
+    /// A grammar builder adds rules or alternatives before adding a pattern.
+    NoPattern { path: String },
+    /// An overlay or an edit of a parser refers to a pattern which is
+    /// not in the grammar.
+    UnknownPattern { path: String, find: String },
+    /// A line of a grammar in the text format is invalid. Lines
+    /// and columns are counted in characters from 1.
+    Dsl { line: usize, column: usize, reason: String },
+    /// The bytes are not a grammar in the binary format.
+    InvalidBinary { offset: usize, reason: &'static str },
+    /// The text is not valid TOML.
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    /// The grammar can't be written as TOML.
+    #[cfg(feature = "toml")]
+    TomlWrite(toml::ser::Error),
+    /// The text is not valid YAML, or the grammar can't be written as YAML.
+    #[cfg(feature = "yaml")]
+    Yaml(serde_yaml_ng::Error),
+    /// A grammar file could not be read.
+    #[cfg(feature = "json")]
+    Io { path: String, error: std::io::Error },
+    /// A grammar file is in a format whose cargo feature is disabled.
+    #[cfg(feature = "json")]
+    DisabledFormat { path: String, feature: &'static str },
+    /// A grammar file includes itself, directly or through other files.
+    #[cfg(feature = "json")]
+    IncludeCycle { path: String },
+    /// A grammar has an `include` which is not resolved, because it was
+    /// not loaded from a file.
+    #[cfg(feature = "json")]
+    UnresolvedInclude { path: String },
+    /// An error in one of the files of a grammar.
+    #[cfg(feature = "json")]
+    InFile { file: String, error: Box<GrammarError> },
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "json")]
+            GrammarError::Syntax(err) => write!(f, "invalid json: {}", err),
+            GrammarError::MissingField { path } => write!(f, "{}: missing field", path),
+            GrammarError::InvalidType { path, expected } if path.is_empty() => {
+                write!(f, "expected {}", expected)
+            }
+            GrammarError::InvalidType { path, expected } => {
+                write!(f, "{}: expected {}", path, expected)
+            }
+            GrammarError::EmptyFind { path } | GrammarError::EmptyDelimiter { path } => {
+                write!(f, "{}: must not be empty", path)
+            }
+            GrammarError::UnknownMatchType { path, value } => {
+                write!(f, "{}: unknown match type `{}`", path, value)
+            }
+            GrammarError::UnknownScope { path, value } => {
+                write!(f, "{}: unknown scope `{}`", path, value)
+            }
+            GrammarError::MisplacedScope { path, scope, expected } => {
+                write!(f, "{}: `{}` can only be a {}", path, scope, expected)
+            }
+            GrammarError::ReservedClass { path } => {
+                write!(f, "{}: is the name of a built-in scope", path)
+            }
+            GrammarError::NoPattern { path } => write!(f, "{}: added before any pattern", path),
+            GrammarError::UnknownPattern { path, find } if path.is_empty() => {
+                write!(f, "no pattern `{}` in the grammar", find)
+            }
+            GrammarError::UnknownPattern { path, find } => {
+                write!(f, "{}: no pattern `{}` in the grammar", path, find)
+            }
+            GrammarError::Dsl { line, column, reason } => {
+                write!(f, "line {}, column {}: {}", line, column, reason)
+            }
+            GrammarError::InvalidBinary { offset, reason } => {
+                write!(f, "invalid binary grammar at byte {}: {}", offset, reason)
+            }
+            #[cfg(feature = "toml")]
+            GrammarError::Toml(err) => write!(f, "invalid toml: {}", err),
+            #[cfg(feature = "toml")]
+            GrammarError::TomlWrite(err) => write!(f, "cannot write toml: {}", err),
+            #[cfg(feature = "yaml")]
+            GrammarError::Yaml(err) => write!(f, "invalid yaml: {}", err),
+            #[cfg(feature = "json")]
+            GrammarError::Io { path, error } => write!(f, "{}: {}", path, error),
+            #[cfg(feature = "json")]
+            GrammarError::DisabledFormat { path, feature } => {
+                write!(f, "{}: the `{}` feature is needed to read this format", path, feature)
+            }
+            #[cfg(feature = "json")]
+            GrammarError::IncludeCycle { path } => write!(f, "{}: include cycle", path),
+            #[cfg(feature = "json")]
+            GrammarError::UnresolvedInclude { path } => {
+                write!(f, "{}: includes are only resolved when loading a grammar file", path)
+            }
+            #[cfg(feature = "json")]
+            GrammarError::InFile { file, error } => write!(f, "{}: {}", file, error),
+        }
+    }
+}
+
+impl Error for GrammarError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "json")]
+            GrammarError::Syntax(err) => Some(err),
+            #[cfg(feature = "toml")]
+            GrammarError::Toml(err) => Some(err),
+            #[cfg(feature = "toml")]
+            GrammarError::TomlWrite(err) => Some(err),
+            #[cfg(feature = "yaml")]
+            GrammarError::Yaml(err) => Some(err),
+            #[cfg(feature = "json")]
+            GrammarError::Io { error, .. } => Some(error),
+            #[cfg(feature = "json")]
+            GrammarError::InFile { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
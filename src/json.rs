@@ -0,0 +1,903 @@
+//! Loading grammars from Json and writing them back.
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_json::{json, Map, Value};
+use crate::format::Format;
+use crate::grammar::{Escape, Grammar, GrammarError, Match, MatchType, Pattern, Rule, Scope, BUILTIN_SCOPES};
+use crate::overlay::{Overlay, PatternChange};
+
+/// The character classes of a grammar.
+type Classes = BTreeMap<String, String>;
+
+impl Grammar {
+    /// Parses a `Grammar` from the given Json text.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rupantor::grammar::Grammar;
+    /// let err = Grammar::from_json_str(r#"{ "patterns": [] }"#).unwrap_err();
+    /// assert_eq!(err.to_string(), "vowel: missing field");
+    /// ```
+    pub fn from_json_str(json: &str) -> Result<Grammar, GrammarError> {
+        let value: Value = serde_json::from_str(json).map_err(GrammarError::Syntax)?;
+        Grammar::from_value(&value)
+    }
+
+    /// Creates a `Grammar` from the given Json value. The Json value must
+    /// be a Json Object containing the required values, otherwise an error
+    /// naming the offending location is returned.
+    pub fn from_value(rule: &Value) -> Result<Grammar, GrammarError> {
+        if rule.get("include").is_some() {
+            return Err(GrammarError::UnresolvedInclude { path: "include".to_string() });
+        }
+
+        let mut classes = Classes::new();
+        if rule.get("classes").is_some() {
+            let object = field(rule, "", "classes")?
+                .as_object()
+                .ok_or_else(|| GrammarError::InvalidType { path: "classes".to_string(), expected: "an object" })?;
+            for (name, characters) in object {
+                let path = format!("classes.{}", name);
+                if BUILTIN_SCOPES.contains(&name.as_str()) {
+                    return Err(GrammarError::ReservedClass { path });
+                }
+                let characters = characters
+                    .as_str()
+                    .ok_or(GrammarError::InvalidType { path, expected: "a string" })?;
+                classes.insert(name.clone(), characters.to_string());
+            }
+        }
+
+        let patterns = array(rule, "", "patterns")?
+            .iter()
+            .enumerate()
+            .map(|(i, pattern)| Pattern::from_value(pattern, &format!("patterns[{}]", i), &classes))
+            .collect::<Result<_, _>>()?;
+
+        let verbatim = match rule.get("verbatim") {
+            Some(_) => array(rule, "", "verbatim")?
+                .iter()
+                .enumerate()
+                .map(|(i, escape)| Escape::from_value(escape, &format!("verbatim[{}]", i)))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let code_point = match rule.get("codepoint") {
+            Some(escape) => Some(Escape::from_value(escape, "codepoint")?),
+            None => None,
+        };
+
+        Ok(Grammar {
+            patterns,
+            vowel: string(rule, "", "vowel")?.to_string(),
+            consonant: string(rule, "", "consonant")?.to_string(),
+            number: string(rule, "", "number")?.to_string(),
+            punctuation: match rule.get("punctuation") {
+                Some(_) => Some(string(rule, "", "punctuation")?.to_string()),
+                None => None,
+            },
+            number_boundary: match rule.get("numberboundary") {
+                Some(value) => value.as_bool().ok_or(GrammarError::InvalidType {
+                    path: "numberboundary".to_string(),
+                    expected: "a boolean",
+                })?,
+                None => true,
+            },
+            classes,
+            case_sensitive: string(rule, "", "casesensitive")?.to_string(),
+            verbatim,
+            code_point,
+        })
+    }
+}
+
+impl Grammar {
+    /// Converts the grammar into Json, in the schema read by
+    /// [`from_value`](#method.from_value). Optional fields are
+    /// left out when they have their default value.
+    pub fn to_value(&self) -> Value {
+        let mut grammar = Map::new();
+        grammar.insert("casesensitive".to_string(), Value::from(self.case_sensitive.as_str()));
+        if !self.classes.is_empty() {
+            grammar.insert("classes".to_string(), json!(self.classes));
+        }
+        if let Some(escape) = &self.code_point {
+            grammar.insert("codepoint".to_string(), escape.to_value());
+        }
+        grammar.insert("consonant".to_string(), Value::from(self.consonant.as_str()));
+        grammar.insert("number".to_string(), Value::from(self.number.as_str()));
+        if !self.number_boundary {
+            grammar.insert("numberboundary".to_string(), Value::from(false));
+        }
+        grammar.insert("patterns".to_string(), self.patterns.iter().map(Pattern::to_value).collect());
+        if let Some(punctuation) = &self.punctuation {
+            grammar.insert("punctuation".to_string(), Value::from(punctuation.as_str()));
+        }
+        if !self.verbatim.is_empty() {
+            grammar.insert("verbatim".to_string(), self.verbatim.iter().map(Escape::to_value).collect());
+        }
+        grammar.insert("vowel".to_string(), Value::from(self.vowel.as_str()));
+        Value::Object(grammar)
+    }
+
+    /// Writes the grammar as canonical Json: the fields of
+    /// [`to_value`](#method.to_value) with sorted keys, indented by
+    /// three spaces and ending with a newline. The patterns are written
+    /// in the order of [`PhoneticParser::grammar`](../parser/struct.PhoneticParser.html#method.grammar),
+    /// without the ones whose `find` is used by an earlier pattern. Grammars
+    /// which convert the same way are always written the same way, so the
+    /// output diffs cleanly.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rupantor::grammar::Grammar;
+    /// let json = r#"{ "vowel": "aeiou", "consonant": "bcdfghjklmnpqrstvwxyz",
+    ///     "number": "0123456789", "casesensitive": "", "patterns": [] }"#;
+    /// let grammar = Grammar::from_json_str(json).unwrap();
+    /// assert!(grammar.to_json().starts_with("{\n   \"casesensitive\": \"\",\n"));
+    /// assert_eq!(Grammar::from_json_str(&grammar.to_json()).unwrap(), grammar);
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut grammar = self.clone();
+        crate::parser::sort_patterns(&mut grammar.patterns);
+        canonical(&grammar.to_value())
+    }
+}
+
+impl Grammar {
+    /// Loads a grammar from a file, along with the files it includes. The
+    /// [`Format`](../format/enum.Format.html) of every file is picked from its
+    /// extension, falling back to Json. A TOML or YAML file is an error
+    /// when the feature of its format is disabled.
+    ///
+    /// A grammar file may list other grammar files in an `include` array,
+    /// relative to the including file. They are merged in order before the
+    /// file itself: their patterns come first, their `classes` and `verbatim`
+    /// escapes are combined, and their other fields apply unless a later file
+    /// sets them. The merged grammar is then validated as a whole, so a part
+    /// may lack the required fields or use classes of another part.
+    ///
+    /// A file is included at most once. Including a file from itself, directly
+    /// or through other files, is an error. Errors found in a single file are
+    /// returned as [`InFile`](enum.GrammarError.html#variant.InFile).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use rupantor::grammar::Grammar;
+    /// # use rupantor::parser::PhoneticParser;
+    /// // bengali.json: { "include": ["vowels.json", "consonants.json"], ... }
+    /// let grammar = Grammar::load("grammars/bengali.json").unwrap();
+    /// let parser = PhoneticParser::from_grammar(grammar);
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Grammar, GrammarError> {
+        let mut parts = Vec::new();
+        collect(path.as_ref(), &mut Vec::new(), &mut HashSet::new(), &mut parts)?;
+
+        let mut merged = Map::new();
+        for (file, part) in &parts {
+            merge(&mut merged, part).map_err(|error| in_file(file, error))?;
+        }
+        merged.insert("patterns".to_string(), json!([]));
+        let mut grammar = Grammar::from_value(&Value::Object(merged))?;
+
+        for (file, part) in &parts {
+            if part.get("patterns").is_some() {
+                for (i, pattern) in array(part, "", "patterns").map_err(|error| in_file(file, error))?.iter().enumerate() {
+                    let pattern = Pattern::from_value(pattern, &format!("patterns[{}]", i), &grammar.classes)
+                        .map_err(|error| in_file(file, error))?;
+                    grammar.patterns.push(pattern);
+                }
+            }
+        }
+
+        Ok(grammar)
+    }
+}
+
+/// Reads the grammar file at `path` and, depth first, the files it
+/// includes into `parts`. `stack` holds the files being read and `done`
+/// the files which were read completely.
+fn collect(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    done: &mut HashSet<PathBuf>,
+    parts: &mut Vec<(String, Value)>,
+) -> Result<(), GrammarError> {
+    let file = path.display().to_string();
+    let io_error = |error| GrammarError::Io { path: file.clone(), error };
+    let canonical = path.canonicalize().map_err(io_error)?;
+    if stack.contains(&canonical) {
+        return Err(GrammarError::IncludeCycle { path: file });
+    }
+    if done.contains(&canonical) {
+        return Ok(());
+    }
+
+    if let Some(feature) = Format::disabled_feature(path) {
+        return Err(GrammarError::DisabledFormat { path: file, feature });
+    }
+    let text = fs::read_to_string(path).map_err(io_error)?;
+    let format = Format::from_path(path).unwrap_or(Format::Json);
+    let part = format.parse(&text).map_err(|error| in_file(&file, error))?;
+
+    stack.push(canonical);
+    if part.get("include").is_some() {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for (i, include) in array(&part, "", "include").map_err(|error| in_file(&file, error))?.iter().enumerate() {
+            let include = include.as_str().ok_or_else(|| {
+                in_file(&file, GrammarError::InvalidType { path: format!("include[{}]", i), expected: "a string" })
+            })?;
+            collect(&dir.join(include), stack, done, parts)?;
+        }
+    }
+    done.extend(stack.pop());
+
+    parts.push((file, part));
+    Ok(())
+}
+
+/// Merges the fields of a grammar file except its patterns and includes.
+fn merge(merged: &mut Map<String, Value>, part: &Value) -> Result<(), GrammarError> {
+    let object = part.as_object().ok_or(GrammarError::InvalidType { path: String::new(), expected: "an object" })?;
+    for (key, value) in object {
+        match key.as_str() {
+            "patterns" => {
+                array(part, "", "patterns")?;
+            }
+            "include" => {}
+            "classes" => {
+                let classes = value
+                    .as_object()
+                    .ok_or_else(|| GrammarError::InvalidType { path: "classes".to_string(), expected: "an object" })?;
+                if let Value::Object(merged) = merged.entry("classes").or_insert_with(|| json!({})) {
+                    merged.extend(classes.clone());
+                }
+            }
+            "verbatim" => {
+                let escapes = array(part, "", "verbatim")?;
+                if let Value::Array(merged) = merged.entry("verbatim").or_insert_with(|| json!([])) {
+                    merged.extend_from_slice(escapes);
+                }
+            }
+            _ => {
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a Json value with sorted keys, indented by three spaces.
+pub(crate) fn canonical(value: &Value) -> String {
+    fn write(value: &Value, indent: usize, json: &mut String) {
+        let (open, close, items): (char, char, Vec<(Option<&String>, &Value)>) = match value {
+            Value::Array(array) => ('[', ']', array.iter().map(|item| (None, item)).collect()),
+            Value::Object(object) => {
+                let mut items: Vec<_> = object.iter().map(|(key, item)| (Some(key), item)).collect();
+                items.sort_by_key(|&(key, _)| key);
+                ('{', '}', items)
+            }
+            _ => {
+                *json += &value.to_string();
+                return;
+            }
+        };
+
+        json.push(open);
+        for (i, (key, item)) in items.iter().enumerate() {
+            *json += if i == 0 { "\n" } else { ",\n" };
+            *json += &"   ".repeat(indent + 1);
+            if let Some(key) = key {
+                *json += &format!("{}: ", Value::from(key.as_str()));
+            }
+            write(item, indent + 1, json);
+        }
+        if !items.is_empty() {
+            json.push('\n');
+            *json += &"   ".repeat(indent);
+        }
+        json.push(close);
+    }
+
+    let mut json = String::new();
+    write(value, 0, &mut json);
+    json.push('\n');
+    json
+}
+
+fn in_file(file: &str, error: GrammarError) -> GrammarError {
+    GrammarError::InFile { file: file.to_string(), error: Box::new(error) }
+}
+
+impl Overlay {
+    /// Parses an `Overlay` from the given Json text.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rupantor::avro::AvroPhonetic;
+    /// # use rupantor::overlay::Overlay;
+    /// let overlay = Overlay::from_json_str(r#"{
+    ///     "patterns": [{ "find": "rZ", "replace": "র্য", "rules": [] }],
+    ///     "remove": ["Sc"]
+    /// }"#).unwrap();
+    ///
+    /// let mut grammar = AvroPhonetic::new().grammar();
+    /// let changes = grammar.apply(&overlay).unwrap();
+    /// assert_eq!(changes.len(), 3);
+    /// ```
+    pub fn from_json_str(json: &str) -> Result<Overlay, GrammarError> {
+        let value: Value = serde_json::from_str(json).map_err(GrammarError::Syntax)?;
+        Overlay::from_value(&value)
+    }
+
+    /// Creates an `Overlay` from the given Json value. Both `patterns` and
+    /// `remove` are optional. The classes used by rules are only checked
+    /// when the overlay is applied to a grammar.
+    pub fn from_value(overlay: &Value) -> Result<Overlay, GrammarError> {
+        let patterns = match overlay.get("patterns") {
+            Some(_) => array(overlay, "", "patterns")?
+                .iter()
+                .enumerate()
+                .map(|(i, pattern)| PatternChange::from_value(pattern, &format!("patterns[{}]", i)))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let remove = match overlay.get("remove") {
+            Some(_) => array(overlay, "", "remove")?
+                .iter()
+                .enumerate()
+                .map(|(i, find)| {
+                    find.as_str().map(str::to_string).ok_or_else(|| GrammarError::InvalidType {
+                        path: format!("remove[{}]", i),
+                        expected: "a string",
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(Overlay { patterns, remove })
+    }
+}
+
+impl PatternChange {
+    fn from_value(pattern: &Value, path: &str) -> Result<PatternChange, GrammarError> {
+        let find = string(pattern, path, "find")?;
+        if find.is_empty() {
+            return Err(GrammarError::EmptyFind { path: join(path, "find") });
+        }
+        let rules = |key| -> Result<Option<Vec<Rule>>, GrammarError> {
+            match pattern.get(key) {
+                Some(_) => array(pattern, path, key)?
+                    .iter()
+                    .enumerate()
+                    .map(|(i, rule)| Rule::from_value(rule, &format!("{}.{}[{}]", path, key, i), None))
+                    .collect::<Result<_, _>>()
+                    .map(Some),
+                None => Ok(None),
+            }
+        };
+
+        Ok(PatternChange {
+            find: find.to_string(),
+            replace: match pattern.get("replace") {
+                Some(_) => Some(string(pattern, path, "replace")?.to_string()),
+                None => None,
+            },
+            alternatives: match pattern.get("alternatives") {
+                Some(_) => Some(alternatives(pattern, path)?),
+                None => None,
+            },
+            rules: rules("rules")?,
+            add_rules: rules("addrules")?.unwrap_or_default(),
+        })
+    }
+}
+
+impl Escape {
+    fn from_value(escape: &Value, path: &str) -> Result<Escape, GrammarError> {
+        let delimiter = |key| match string(escape, path, key)? {
+            "" => Err(GrammarError::EmptyDelimiter { path: join(path, key) }),
+            delimiter => Ok(delimiter.to_string()),
+        };
+
+        Ok(Escape {
+            open: delimiter("open")?,
+            close: delimiter("close")?,
+        })
+    }
+}
+
+impl Escape {
+    fn to_value(&self) -> Value {
+        json!({ "close": self.close, "open": self.open })
+    }
+}
+
+impl Pattern {
+    fn to_value(&self) -> Value {
+        let mut pattern = Map::new();
+        if !self.alternatives.is_empty() {
+            pattern.insert("alternatives".to_string(), json!(self.alternatives));
+        }
+        pattern.insert("find".to_string(), Value::from(self.find.as_str()));
+        pattern.insert("replace".to_string(), Value::from(self.replace.as_str()));
+        pattern.insert("rules".to_string(), self.rules.iter().map(Rule::to_value).collect());
+        Value::Object(pattern)
+    }
+
+    fn from_value(pattern: &Value, path: &str, classes: &Classes) -> Result<Pattern, GrammarError> {
+        let find = string(pattern, path, "find")?;
+        if find.is_empty() {
+            return Err(GrammarError::EmptyFind { path: join(path, "find") });
+        }
+
+        let rules = array(pattern, path, "rules")?
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| Rule::from_value(rule, &format!("{}.rules[{}]", path, i), Some(classes)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Pattern {
+            find: find.to_string(),
+            replace: string(pattern, path, "replace")?.to_string(),
+            alternatives: alternatives(pattern, path)?,
+            rules,
+        })
+    }
+}
+
+impl Rule {
+    fn to_value(&self) -> Value {
+        let mut rule = Map::new();
+        if !self.alternatives.is_empty() {
+            rule.insert("alternatives".to_string(), json!(self.alternatives));
+        }
+        rule.insert("matches".to_string(), self.matches.iter().map(Match::to_value).collect());
+        rule.insert("replace".to_string(), Value::from(self.replace.as_str()));
+        Value::Object(rule)
+    }
+
+    fn from_value(rule: &Value, path: &str, classes: Option<&Classes>) -> Result<Rule, GrammarError> {
+        let matches = array(rule, path, "matches")?
+            .iter()
+            .enumerate()
+            .map(|(i, _match)| Match::from_value(_match, &format!("{}.matches[{}]", path, i), classes))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Rule {
+            matches,
+            replace: string(rule, path, "replace")?.to_string(),
+            alternatives: alternatives(rule, path)?,
+        })
+    }
+}
+
+impl Match {
+    /// Parses a match. Without `classes`, every name which
+    /// is not a built-in scope is taken as a class.
+    fn from_value(_match: &Value, path: &str, classes: Option<&Classes>) -> Result<Match, GrammarError> {
+        let kind = match string(_match, path, "type")? {
+            "prefix" => MatchType::Prefix,
+            "suffix" => MatchType::Suffix,
+            other => {
+                return Err(GrammarError::UnknownMatchType {
+                    path: join(path, "type"),
+                    value: other.to_string(),
+                })
+            }
+        };
+
+        let mut scope = string(_match, path, "scope")?;
+        let mut negative = false;
+
+        // Handle Negative
+        if scope.starts_with('!') {
+            negative = true;
+            scope = &scope[1..];
+        }
+
+        let scope = match scope {
+            "punctuation" => Scope::Punctuation,
+            "vowel" => Scope::Vowel,
+            "consonant" => Scope::Consonant,
+            "number" => Scope::Number,
+            "whitespace" => Scope::Whitespace,
+            "start" => Scope::Start,
+            "end" => Scope::End,
+            "exact" => exact(_match, path)?,
+            class if classes.is_none_or(|classes| classes.contains_key(class)) => Scope::Class(class.to_string()),
+            other => {
+                return Err(GrammarError::UnknownScope {
+                    path: join(path, "scope"),
+                    value: other.to_string(),
+                })
+            }
+        };
+
+        let _match = Match { kind, scope, negative };
+        if let Some((scope, expected)) = _match.misplaced() {
+            return Err(GrammarError::MisplacedScope { path: join(path, "type"), scope, expected });
+        }
+        Ok(_match)
+    }
+}
+
+impl Match {
+    /// Converts the match into its Json form.
+    pub(crate) fn to_value(&self) -> Value {
+        let kind = match self.kind {
+            MatchType::Prefix => "prefix",
+            MatchType::Suffix => "suffix",
+        };
+        let scope = match &self.scope {
+            Scope::Punctuation => "punctuation",
+            Scope::Vowel => "vowel",
+            Scope::Consonant => "consonant",
+            Scope::Number => "number",
+            Scope::Whitespace => "whitespace",
+            Scope::Start => "start",
+            Scope::End => "end",
+            Scope::Class(class) => class,
+            Scope::Exact(_) | Scope::ExactAny(_) => "exact",
+        };
+
+        let mut value = json!({
+            "type": kind,
+            "scope": if self.negative { format!("!{}", scope) } else { scope.to_string() },
+        });
+        match &self.scope {
+            Scope::Exact(exact) => value["value"] = Value::from(exact.as_str()),
+            Scope::ExactAny(exact) => value["value"] = Value::from(exact.clone()),
+            _ => {}
+        }
+        value
+    }
+}
+
+/// Reads the `value` of an `exact` scope, a string or an array of strings.
+fn exact(_match: &Value, path: &str) -> Result<Scope, GrammarError> {
+    let value = field(_match, path, "value")?;
+    if let Some(value) = value.as_str() {
+        return Ok(Scope::Exact(value.to_string()));
+    }
+
+    let invalid = || GrammarError::InvalidType {
+        path: join(path, "value"),
+        expected: "a string or an array of strings",
+    };
+    value
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|value| value.as_str().map(str::to_string).ok_or_else(invalid))
+        .collect::<Result<_, _>>()
+        .map(Scope::ExactAny)
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn field<'a>(value: &'a Value, path: &str, key: &str) -> Result<&'a Value, GrammarError> {
+    value
+        .get(key)
+        .ok_or_else(|| GrammarError::MissingField { path: join(path, key) })
+}
+
+fn string<'a>(value: &'a Value, path: &str, key: &str) -> Result<&'a str, GrammarError> {
+    field(value, path, key)?
+        .as_str()
+        .ok_or_else(|| GrammarError::InvalidType { path: join(path, key), expected: "a string" })
+}
+
+fn array<'a>(value: &'a Value, path: &str, key: &str) -> Result<&'a [Value], GrammarError> {
+    field(value, path, key)?
+        .as_array()
+        .map(Vec::as_slice)
+        .ok_or_else(|| GrammarError::InvalidType { path: join(path, key), expected: "an array" })
+}
+
+/// Reads the optional `alternatives` list of strings.
+fn alternatives(value: &Value, path: &str) -> Result<Vec<String>, GrammarError> {
+    if value.get("alternatives").is_none() {
+        return Ok(Vec::new());
+    }
+
+    array(value, path, "alternatives")?
+        .iter()
+        .enumerate()
+        .map(|(i, alternative)| {
+            alternative.as_str().map(str::to_string).ok_or_else(|| GrammarError::InvalidType {
+                path: format!("{}[{}]", join(path, "alternatives"), i),
+                expected: "a string",
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_value() {
+        let grammar = Grammar::from_json_str(include_str!("AvroPhonetic.json")).unwrap();
+
+        assert_eq!(grammar.vowel, "aeiou");
+        assert_eq!(grammar.case_sensitive, "oiudgjnrstyz");
+        assert_eq!(grammar.patterns[0].find, "NgkSh");
+
+        let o = grammar.patterns.iter().find(|p| p.find == "o").unwrap();
+        assert_eq!(o.replace, "");
+        assert_eq!(o.rules[0].replace, "ও");
+        assert!(o.alternatives.is_empty());
+        assert_eq!(o.rules[0].matches[1], Match {
+            kind: MatchType::Prefix,
+            scope: Scope::Exact("o".to_string()),
+            negative: true,
+        });
+
+        let s = grammar.patterns.iter().find(|p| p.find == "s").unwrap();
+        assert_eq!(s.alternatives, vec!["শ", "ষ"]);
+    }
+
+    #[test]
+    fn test_negative_exact() {
+        let json = json!({
+            "type": "suffix",
+            "scope": "!exact",
+            "value": "`"
+        });
+        assert_eq!(Match::from_value(&json, "", Some(&Classes::new())).unwrap(), Match {
+            kind: MatchType::Suffix,
+            scope: Scope::Exact("`".to_string()),
+            negative: true,
+        });
+    }
+
+    #[test]
+    fn test_match_to_value() {
+        for value in [
+            json!({ "type": "suffix", "scope": "!exact", "value": "`" }),
+            json!({ "type": "prefix", "scope": "consonant" }),
+            json!({ "type": "suffix", "scope": "exact", "value": ["kh", "gh"] }),
+            json!({ "type": "prefix", "scope": "!start" }),
+        ].iter() {
+            assert_eq!(&Match::from_value(value, "", Some(&Classes::new())).unwrap().to_value(), value);
+        }
+    }
+
+    fn grammar_with(pattern: Value) -> Value {
+        json!({
+            "vowel": "aeiou",
+            "consonant": "bcdfghjklmnpqrstvwxyz",
+            "number": "1234567890",
+            "casesensitive": "",
+            "patterns": [{ "find": "a", "replace": "আ", "rules": [] }, pattern]
+        })
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = Grammar::from_value(&grammar_with(json!({
+            "find": "o",
+            "replace": "অ",
+            "rules": [{ "matches": [{ "type": "prefix", "scope": "!digit" }], "replace": "" }]
+        }))).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[1].rules[0].matches[0].scope: unknown scope `digit`");
+
+        let err = Grammar::from_value(&grammar_with(json!({
+            "find": "o",
+            "replace": "অ",
+            "rules": [{ "matches": [{ "type": "infix", "scope": "vowel" }], "replace": "" }]
+        }))).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[1].rules[0].matches[0].type: unknown match type `infix`");
+
+        let err = Grammar::from_value(&grammar_with(json!({
+            "find": "o",
+            "replace": "অ",
+            "rules": [{ "matches": [{ "type": "prefix", "scope": "!end" }], "replace": "" }]
+        }))).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[1].rules[0].matches[0].type: `end` can only be a suffix");
+
+        let err = Grammar::from_value(&grammar_with(json!({
+            "find": "o",
+            "replace": "অ",
+            "rules": [{ "matches": [{ "type": "suffix", "scope": "exact" }], "replace": "" }]
+        }))).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[1].rules[0].matches[0].value: missing field");
+
+        let err = Grammar::from_value(&grammar_with(json!({ "find": "o", "replace": 1, "rules": [] })))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "patterns[1].replace: expected a string");
+
+        let err = Grammar::from_value(&grammar_with(json!({ "find": "", "replace": "", "rules": [] })))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "patterns[1].find: must not be empty");
+
+        let err = Grammar::from_value(&grammar_with(json!({
+            "find": "o",
+            "replace": "অ",
+            "alternatives": ["ও", 1],
+            "rules": []
+        }))).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[1].alternatives[1]: expected a string");
+
+        let mut json = grammar_with(json!({ "find": "o", "replace": "অ", "rules": [] }));
+        json["verbatim"] = json!([{ "open": "{", "close": "}" }, { "open": "<", "close": "" }]);
+        let err = Grammar::from_value(&json).unwrap_err();
+        assert_eq!(err.to_string(), "verbatim[1].close: must not be empty");
+
+        json["verbatim"] = json!([]);
+        json["codepoint"] = json!({ "open": "#" });
+        let err = Grammar::from_value(&json).unwrap_err();
+        assert_eq!(err.to_string(), "codepoint.close: missing field");
+
+        let err = Grammar::from_value(&grammar_with(json!({
+            "find": "o",
+            "replace": "অ",
+            "rules": [{ "matches": [{ "type": "prefix", "scope": "aspirate" }], "replace": "" }]
+        }))).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[1].rules[0].matches[0].scope: unknown scope `aspirate`");
+
+        let err = Grammar::from_value(&grammar_with(json!({
+            "find": "o",
+            "replace": "অ",
+            "rules": [{ "matches": [{ "type": "prefix", "scope": "exact", "value": ["k", 1] }], "replace": "" }]
+        }))).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[1].rules[0].matches[0].value: expected a string or an array of strings");
+
+        let mut json = grammar_with(json!({ "find": "o", "replace": "অ", "rules": [] }));
+        json["classes"] = json!({ "aspirate": ["h"] });
+        let err = Grammar::from_value(&json).unwrap_err();
+        assert_eq!(err.to_string(), "classes.aspirate: expected a string");
+
+        let err = Grammar::from_json_str("{ \"patterns\": ").unwrap_err();
+        assert!(matches!(err, GrammarError::Syntax(_)));
+    }
+
+    #[test]
+    fn test_overlay() {
+        let overlay = Overlay::from_value(&json!({
+            "patterns": [
+                { "find": "w", "alternatives": ["ও"] },
+                { "find": "x", "addrules": [{ "matches": [{ "type": "prefix", "scope": "aspirate" }], "replace": "" }] }
+            ]
+        })).unwrap();
+        assert!(overlay.remove.is_empty());
+        assert_eq!(overlay.patterns[0].replace, None);
+        assert_eq!(overlay.patterns[0].alternatives, Some(vec!["ও".to_string()]));
+        assert_eq!(overlay.patterns[1].add_rules[0].matches[0].scope, Scope::Class("aspirate".to_string()));
+
+        let err = Overlay::from_value(&json!({ "remove": ["w", 1] })).unwrap_err();
+        assert_eq!(err.to_string(), "remove[1]: expected a string");
+
+        let err = Overlay::from_value(&json!({ "patterns": [{ "find": "w", "rules": {} }] })).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[0].rules: expected an array");
+
+        let err = Overlay::from_value(&json!({
+            "patterns": [{ "find": "w", "addrules": [{ "matches": [{ "type": "prefix", "scope": "!exact" }], "replace": "" }] }]
+        })).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[0].addrules[0].matches[0].value: missing field");
+    }
+
+    /// Writes the given grammar files into a new temporary directory.
+    fn grammar_dir(name: &str, files: &[(&str, Value)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("rupantor-{}-{}", name, std::process::id()));
+        for (file, value) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, value.to_string()).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = grammar_dir("load", &[
+            ("main.json", json!({
+                "include": ["parts/vowels.json", "parts/consonants.json"],
+                "patterns": [{ "find": "kh", "replace": "খ", "rules": [] }],
+                "vowel": "aeiou",
+                "consonant": "bcdfghjklmnpqrstvwxyz",
+                "number": "0123456789",
+                "casesensitive": ""
+            })),
+            ("parts/vowels.json", json!({
+                "include": ["common.json"],
+                "patterns": [{ "find": "a", "replace": "আ", "rules": [] }],
+                "vowel": "a"
+            })),
+            ("parts/consonants.json", json!({
+                "include": ["common.json"],
+                "patterns": [{
+                    "find": "k",
+                    "replace": "ক",
+                    "rules": [{ "matches": [{ "type": "suffix", "scope": "aspirate" }], "replace": "" }]
+                }]
+            })),
+            ("parts/common.json", json!({ "classes": { "aspirate": "h" }, "casesensitive": "oO" })),
+        ]);
+
+        let grammar = Grammar::load(dir.join("main.json")).unwrap();
+        let finds: Vec<&str> = grammar.patterns.iter().map(|pattern| pattern.find.as_str()).collect();
+        assert_eq!(finds, vec!["a", "k", "kh"]);
+        assert_eq!(grammar.vowel, "aeiou");
+        assert_eq!(grammar.case_sensitive, "");
+        assert_eq!(grammar.classes["aspirate"], "h");
+        assert_eq!(crate::parser::PhoneticParser::from_grammar(grammar).convert("kakh"), "কআখ");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let dir = grammar_dir("load-errors", &[
+            ("a.json", json!({ "include": ["b.json"] })),
+            ("b.json", json!({ "include": ["a.json"] })),
+            ("c.json", {
+                let mut json = grammar_with(json!({ "find": "o", "replace": "অ", "rules": [] }));
+                json["include"] = json!(["d.json"]);
+                json
+            }),
+            ("d.json", json!({ "patterns": [{ "find": "k", "replace": 1, "rules": [] }] })),
+            ("e.json", json!({ "include": ["missing.json"] })),
+            ("f.json", json!({ "include": ["part.yml"] })),
+            ("part.yml", json!({ "vowel": "aeiou" })),
+        ]);
+        let file = |name: &str| dir.join(name).display().to_string();
+
+        let err = Grammar::load(dir.join("a.json")).unwrap_err();
+        assert_eq!(err.to_string(), format!("{}: include cycle", file("a.json")));
+
+        let err = Grammar::load(dir.join("c.json")).unwrap_err();
+        assert_eq!(err.to_string(), format!("{}: patterns[0].replace: expected a string", file("d.json")));
+
+        let err = Grammar::load(dir.join("e.json")).unwrap_err();
+        assert!(matches!(err, GrammarError::Io { path, .. } if path == file("missing.json")));
+
+        #[cfg(not(feature = "yaml"))]
+        {
+            let err = Grammar::load(dir.join("f.json")).unwrap_err();
+            assert_eq!(err.to_string(), format!("{}: the `yaml` feature is needed to read this format", file("part.yml")));
+        }
+
+        let err = Grammar::from_value(&json!({ "include": ["a.json"] })).unwrap_err();
+        assert_eq!(err.to_string(), "include: includes are only resolved when loading a grammar file");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_to_json() {
+        let avro = include_str!("AvroPhonetic.json");
+        let grammar = Grammar::from_json_str(avro).unwrap();
+        assert_eq!(grammar.to_value(), serde_json::from_str::<Value>(avro).unwrap());
+
+        let json = grammar.to_json();
+        let sorted = crate::parser::PhoneticParser::from_grammar(grammar.clone()).grammar().clone();
+        assert_eq!(Grammar::from_json_str(&json).unwrap(), sorted);
+        assert!(json.contains("\n   \"patterns\": [\n      {\n         \"find\": \"NgkSh\",\n"));
+        assert!(json.contains("\"rules\": []"));
+
+        // The order of the patterns doesn't matter.
+        let mut shuffled = sorted.clone();
+        shuffled.patterns.reverse();
+        shuffled.patterns.rotate_left(100);
+        assert_eq!(shuffled.to_json(), json);
+        assert_eq!(sorted.to_json(), json);
+
+        let extended = crate::parser::extended(sorted);
+        let json = extended.to_json();
+        assert_eq!(Grammar::from_json_str(&json).unwrap(), extended);
+        assert!(json.contains("\"codepoint\": {\n      \"close\": \"}\",\n      \"open\": \"\\\\u{\"\n   }"));
+
+        assert_eq!(canonical(&json!({ "b": [], "a": {} })), "{\n   \"a\": {},\n   \"b\": []\n}\n");
+    }
+}
@@ -22,10 +22,43 @@
 //! struct and a Avro Phonetic [grammar file](https://github.com/OpenBangla/rupantor-rs/blob/master/src/AvroPhonetic.json)
 //! internally to do the conversion.
 //! 
+//! # Cargo features
+//! * `json` (enabled by default): loading grammars from Json with
+//!   [`Grammar::from_json_str`](grammar/struct.Grammar.html#method.from_json_str)
+//!   and [`PhoneticParser::new`](parser/struct.PhoneticParser.html#method.new),
+//!   writing them as canonical Json with [`Grammar::to_json`](grammar/struct.Grammar.html#method.to_json),
+//!   grammar files with includes with [`Grammar::load`](grammar/struct.Grammar.html#method.load),
+//!   overlays with [`Overlay::from_json_str`](overlay/struct.Overlay.html#method.from_json_str),
+//!   autocorrect tables with [`Autocorrect::load_json`](autocorrect/struct.Autocorrect.html#method.load_json),
+//!   and serializing conversion traces with [`Trace::to_json`](trace/struct.Trace.html#method.to_json).
+//!   [`AvroPhonetic`](avro/struct.AvroPhonetic.html) uses an embedded precompiled
+//!   grammar, so it is available without this feature and without `serde_json`.
+//! * `toml` and `yaml`: loading grammars from TOML with
+//!   [`Grammar::from_toml_str`](grammar/struct.Grammar.html#method.from_toml_str) and from YAML with
+//!   [`Grammar::from_yaml_str`](grammar/struct.Grammar.html#method.from_yaml_str), and converting
+//!   grammars between the formats with [`format::convert`](format/fn.convert.html). Both enable `json`.
+//!
 //! The phonetic conversion algorithm was actually implemented by
 //! [Rifat Nabi](https://github.com/torifat) in [JavaScript](https://github.com/torifat/jsAvroPhonetic)
 //! and [ObjectiveC](https://github.com/torifat/iAvro/blob/master/AvroParser.m).
 //! This crate is the Rust port of that phonetic conversion algorithm.
 
+pub mod autocorrect;
+pub mod builder;
+pub mod dictionary;
+pub mod dsl;
+#[cfg(feature = "json")]
+pub mod format;
+pub mod grammar;
+pub mod lint;
+pub mod overlay;
 pub mod parser;
+pub mod reverse;
+pub mod session;
+pub mod spans;
+pub mod trace;
+mod compiled;
+#[cfg(feature = "json")]
+mod json;
+mod trie;
 pub mod avro;
\ No newline at end of file
@@ -0,0 +1,333 @@
+//! Static checks for hand-written grammars.
+//!
+//! Mistakes in a grammar, like a duplicated `find` or a rule which is placed
+//! after an unconditional rule, silently change the conversion output.
+//! [`lint`](fn.lint.html) reports such problems of a loaded grammar.
+//!
+//! # Example
+//! ```rust
+//! use rupantor::avro::AvroPhonetic;
+//! use rupantor::lint::{lint, Severity};
+//!
+//! let grammar = AvroPhonetic::new().grammar();
+//! for diagnostic in lint(&grammar) {
+//!     if diagnostic.severity == Severity::Error {
+//!         println!("{}", diagnostic);
+//!     }
+//! }
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use crate::grammar::{Grammar, Match, Pattern, Rule, Scope};
+
+/// How serious a reported problem is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Harmless, but probably not intended.
+    Info,
+    /// Part of the grammar never has any effect.
+    Warning,
+    /// The conversion output depends on undefined behaviour.
+    Error,
+}
+
+/// The kind of a reported problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintKind {
+    /// More than one pattern has the same `find`.
+    DuplicateFind,
+    /// A rule can never be applied.
+    UnreachableRule,
+    /// An `exact` match looks for text which can never appear in the input.
+    ImpossibleExact,
+    /// A character class is not used by any rule.
+    UnusedClass,
+    /// A pattern can never be matched.
+    ShadowedPattern,
+}
+
+/// A problem found in a grammar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: LintKind,
+    /// Json path of the offending value, e.g. `patterns[12].rules[1]`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.path, self.message)
+    }
+}
+
+/// Checks the given grammar and returns the problems found,
+/// in the order they appear in the grammar.
+pub fn lint(grammar: &Grammar) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+
+    for (index, pattern) in grammar.patterns.iter().enumerate() {
+        let path = format!("patterns[{}]", index);
+
+        match seen.get(pattern.find.as_str()) {
+            Some(first) => diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                kind: LintKind::DuplicateFind,
+                path: format!("{}.find", path),
+                message: format!("`{}` is already defined by patterns[{}]", pattern.find, first),
+            }),
+            None => {
+                seen.insert(&pattern.find, index);
+            }
+        }
+
+        if let Some(character) = unreachable_char(grammar, &pattern.find) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                kind: LintKind::ShadowedPattern,
+                path: format!("{}.find", path),
+                message: format!(
+                    "`{}` can never be matched because `{}` is lowercased before conversion",
+                    pattern.find, character
+                ),
+            });
+        }
+
+        lint_rules(grammar, pattern, &path, &mut diagnostics);
+    }
+
+    lint_classes(grammar, &mut diagnostics);
+
+    diagnostics
+}
+
+fn lint_rules(grammar: &Grammar, pattern: &Pattern, path: &str, diagnostics: &mut Vec<Diagnostic>) {
+    for (index, rule) in pattern.rules.iter().enumerate() {
+        let rule_path = format!("{}.rules[{}]", path, index);
+        let earlier = &pattern.rules[..index];
+
+        if let Some(position) = earlier.iter().position(|r| r.matches.is_empty()) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                kind: LintKind::UnreachableRule,
+                path: rule_path.clone(),
+                message: format!("rules[{}] has no matches and always applies first", position),
+            });
+        } else if let Some(position) = earlier.iter().position(|r| has_matches_of(rule, r)) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                kind: LintKind::UnreachableRule,
+                path: rule_path.clone(),
+                message: format!("rules[{}] has a subset of the matches and always applies first", position),
+            });
+        } else if let Some(position) = contradiction(rule) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                kind: LintKind::UnreachableRule,
+                path: format!("{}.matches[{}]", rule_path, position),
+                message: "contradicts an earlier match of the same rule".to_string(),
+            });
+        }
+
+        for (position, _match) in rule.matches.iter().enumerate() {
+            let value_path = format!("{}.matches[{}].value", rule_path, position);
+            // A positive match loses what it looks for, a negative one only has no effect.
+            let severity = if _match.negative { Severity::Info } else { Severity::Warning };
+            match &_match.scope {
+                Scope::Exact(value) => {
+                    if let Some(character) = unreachable_char(grammar, value) {
+                        let effect = if _match.negative { "is always satisfied" } else { "can never be satisfied" };
+                        diagnostics.push(Diagnostic {
+                            severity,
+                            kind: LintKind::ImpossibleExact,
+                            path: value_path,
+                            message: format!(
+                                "`{}` {} because `{}` is lowercased before conversion",
+                                value, effect, character
+                            ),
+                        });
+                    }
+                }
+                Scope::ExactAny(values) => {
+                    for (index, value) in values.iter().enumerate() {
+                        if let Some(character) = unreachable_char(grammar, value) {
+                            diagnostics.push(Diagnostic {
+                                severity,
+                                kind: LintKind::ImpossibleExact,
+                                path: format!("{}[{}]", value_path, index),
+                                message: format!(
+                                    "`{}` never matches because `{}` is lowercased before conversion",
+                                    value, character
+                                ),
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn lint_classes(grammar: &Grammar, diagnostics: &mut Vec<Diagnostic>) {
+    let scopes: Vec<&Scope> = grammar
+        .patterns
+        .iter()
+        .flat_map(|pattern| &pattern.rules)
+        .flat_map(|rule| &rule.matches)
+        .map(|_match| &_match.scope)
+        .collect();
+
+    // Without its own class, punctuation is defined as neither a vowel nor a consonant.
+    let punctuation = scopes.contains(&&Scope::Punctuation);
+    let uses = |scope: &Scope| scopes.contains(&scope) || (punctuation && grammar.punctuation.is_none());
+    let classes = [
+        ("vowel", grammar.vowel.as_str(), uses(&Scope::Vowel)),
+        ("consonant", grammar.consonant.as_str(), uses(&Scope::Consonant)),
+        ("number", grammar.number.as_str(), scopes.contains(&&Scope::Number)),
+        ("punctuation", grammar.punctuation.as_deref().unwrap_or(""), punctuation),
+    ];
+
+    let named = grammar.classes.iter().map(|(name, characters)| {
+        let used = scopes.iter().any(|scope| matches!(scope, Scope::Class(class) if class == name));
+        (format!("classes.{}", name), characters.as_str(), used)
+    });
+    let classes = classes
+        .iter()
+        .map(|(name, characters, used)| (name.to_string(), *characters, *used))
+        .chain(named);
+
+    for (path, characters, used) in classes {
+        if !used && !characters.is_empty() {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Info,
+                kind: LintKind::UnusedClass,
+                path,
+                message: "is not used by any rule".to_string(),
+            });
+        }
+    }
+}
+
+/// Returns a character of `text` which never survives the case
+/// normalization of the input.
+fn unreachable_char(grammar: &Grammar, text: &str) -> Option<char> {
+    text.chars().find(|&c| grammar.fix_char(c) != c)
+}
+
+/// Whether `rule` has every match of `earlier`, so it can only
+/// apply where `earlier` applies as well.
+fn has_matches_of(rule: &Rule, earlier: &Rule) -> bool {
+    earlier.matches.iter().all(|m| rule.matches.contains(m))
+}
+
+/// Returns the position of a match which is the negation of
+/// an earlier match of the same rule.
+fn contradiction(rule: &Rule) -> Option<usize> {
+    rule.matches.iter().enumerate().position(|(index, m)| {
+        rule.matches[..index].iter().any(|earlier| {
+            earlier == &Match {
+                negative: !m.negative,
+                ..m.clone()
+            }
+        })
+    })
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use serde_json::json;
+    use crate::grammar::Grammar;
+    use super::*;
+
+    #[test]
+    fn test_avro_grammar() {
+        let grammar = Grammar::from_json_str(include_str!("AvroPhonetic.json")).unwrap();
+        let found: Vec<(LintKind, String)> = lint(&grammar)
+            .into_iter()
+            .map(|d| (d.kind, d.path))
+            .collect();
+
+        assert_eq!(found, vec![
+            (LintKind::ShadowedPattern, "patterns[106].find".to_string()),
+            (LintKind::ShadowedPattern, "patterns[107].find".to_string()),
+            (LintKind::DuplicateFind, "patterns[131].find".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_rules_and_classes() {
+        let grammar = Grammar::from_value(&json!({
+            "vowel": "aeiou",
+            "consonant": "bcdfghjklmnpqrstvwxyz",
+            "number": "1234567890",
+            "casesensitive": "o",
+            "punctuation": " .",
+            "classes": { "aspirate": "h", "space": " " },
+            "patterns": [{
+                "find": "o",
+                "replace": "অ",
+                "rules": [
+                    { "matches": [{ "type": "prefix", "scope": "vowel" }], "replace": "ও" },
+                    { "matches": [{ "type": "prefix", "scope": "vowel" }], "replace": "ঔ" },
+                    {
+                        "matches": [
+                            { "type": "suffix", "scope": "consonant" },
+                            { "type": "suffix", "scope": "!consonant" }
+                        ],
+                        "replace": ""
+                    },
+                    { "matches": [{ "type": "suffix", "scope": "exact", "value": "A" }], "replace": "" },
+                    {
+                        "matches": [
+                            { "type": "suffix", "scope": "consonant" },
+                            { "type": "prefix", "scope": "vowel" }
+                        ],
+                        "replace": ""
+                    },
+                    { "matches": [], "replace": "" },
+                    { "matches": [{ "type": "suffix", "scope": "!exact", "value": "O" }], "replace": "" },
+                    { "matches": [{ "type": "suffix", "scope": "exact", "value": ["a", "A"] }], "replace": "" },
+                    { "matches": [{ "type": "suffix", "scope": "aspirate" }], "replace": "" }
+                ]
+            }]
+        })).unwrap();
+
+        let found: Vec<(Severity, LintKind, String)> = lint(&grammar)
+            .into_iter()
+            .map(|d| (d.severity, d.kind, d.path))
+            .collect();
+
+        assert_eq!(found, vec![
+            (Severity::Warning, LintKind::UnreachableRule, "patterns[0].rules[1]".to_string()),
+            (Severity::Warning, LintKind::UnreachableRule, "patterns[0].rules[2].matches[1]".to_string()),
+            (Severity::Warning, LintKind::ImpossibleExact, "patterns[0].rules[3].matches[0].value".to_string()),
+            (Severity::Warning, LintKind::UnreachableRule, "patterns[0].rules[4]".to_string()),
+            (Severity::Warning, LintKind::UnreachableRule, "patterns[0].rules[6]".to_string()),
+            (Severity::Warning, LintKind::UnreachableRule, "patterns[0].rules[7]".to_string()),
+            (Severity::Warning, LintKind::ImpossibleExact, "patterns[0].rules[7].matches[0].value[1]".to_string()),
+            (Severity::Warning, LintKind::UnreachableRule, "patterns[0].rules[8]".to_string()),
+            (Severity::Info, LintKind::UnusedClass, "number".to_string()),
+            (Severity::Info, LintKind::UnusedClass, "punctuation".to_string()),
+            (Severity::Info, LintKind::UnusedClass, "classes.space".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_display() {
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            kind: LintKind::DuplicateFind,
+            path: "patterns[3].find".to_string(),
+            message: "`Sc` is already defined by patterns[1]".to_string(),
+        };
+        assert_eq!(diagnostic.to_string(), "error: patterns[3].find: `Sc` is already defined by patterns[1]");
+    }
+}
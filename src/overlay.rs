@@ -0,0 +1,297 @@
+//! Changes applied on top of a base grammar.
+//!
+//! An [`Overlay`](struct.Overlay.html) adds, replaces or removes patterns of
+//! a grammar, identified by their `find`, and replaces or adds rules of
+//! existing patterns. This allows tweaking a few mappings of a large grammar
+//! like Avro Phonetic without copying it. With the `json` feature, overlays
+//! are written as Json:
+//!
+//! ```json
+//! {
+//!     "patterns": [
+//!         { "find": "w", "replace": "ও", "rules": [] },
+//!         { "find": "x", "addrules": [{ "matches": [], "replace": "ক্স" }] }
+//!     ],
+//!     "remove": ["rZ"]
+//! }
+//! ```
+//!
+//! A pattern entry sets only the fields it contains: `replace`, `alternatives`
+//! and `rules` replace the values of the existing pattern, while the rules in
+//! `addrules` are checked before its existing rules. An entry whose `find`
+//! is not in the grammar adds a new pattern and must contain `replace`.
+//!
+//! # Example
+//! ```rust
+//! use rupantor::avro::AvroPhonetic;
+//! use rupantor::overlay::{Overlay, PatternChange};
+//! use rupantor::parser::PhoneticParser;
+//!
+//! let overlay = Overlay {
+//!     patterns: vec![PatternChange {
+//!         find: "w".to_string(),
+//!         replace: Some("ও".to_string()),
+//!         rules: Some(Vec::new()),
+//!         ..Default::default()
+//!     }],
+//!     remove: Vec::new(),
+//! };
+//!
+//! let base = AvroPhonetic::new().grammar();
+//! let (parser, reports) = PhoneticParser::with_overlays(base, &[overlay]).unwrap();
+//! assert_eq!(reports[0][0].to_string(), "`w`: replaced `replace`");
+//! assert_eq!(parser.convert("kw"), "কও");
+//! ```
+use std::fmt;
+use crate::grammar::{Grammar, GrammarError, Pattern, Rule};
+use crate::parser::PhoneticParser;
+
+/// Changes to the patterns of a grammar.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overlay {
+    /// Patterns to add or change, in order.
+    pub patterns: Vec<PatternChange>,
+    /// The `find` of every pattern to remove.
+    pub remove: Vec<String>,
+}
+
+/// The change of the pattern with the given `find`. Fields which
+/// are `None` are kept as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatternChange {
+    pub find: String,
+    pub replace: Option<String>,
+    pub alternatives: Option<Vec<String>>,
+    pub rules: Option<Vec<Rule>>,
+    /// Rules which are checked before the existing rules.
+    pub add_rules: Vec<Rule>,
+}
+
+/// What an overlay changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// A new pattern was added.
+    Added,
+    /// A field of an existing pattern was replaced.
+    Replaced,
+    /// Rules were added to an existing pattern.
+    RulesAdded,
+    /// A pattern was removed.
+    Removed,
+}
+
+/// A change made to a pattern by an overlay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// The `find` of the changed pattern.
+    pub find: String,
+    /// For replaced fields, the name of the field.
+    pub field: Option<&'static str>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.kind, self.field) {
+            (ChangeKind::Added, _) => write!(f, "`{}`: added", self.find),
+            (ChangeKind::Replaced, Some(field)) => write!(f, "`{}`: replaced `{}`", self.find, field),
+            (ChangeKind::Replaced, None) => write!(f, "`{}`: replaced", self.find),
+            (ChangeKind::RulesAdded, _) => write!(f, "`{}`: added rules", self.find),
+            (ChangeKind::Removed, _) => write!(f, "`{}`: removed", self.find),
+        }
+    }
+}
+
+impl Grammar {
+    /// Applies an overlay and returns the changes it made, in order.
+    ///
+    /// A changed pattern is the first one with its `find`, which is the one
+    /// used by the parser, while removing a `find` removes all of its
+    /// patterns. If the overlay can't be applied, e.g. because it removes a
+    /// pattern which doesn't exist, the grammar is left unchanged and the
+    /// error names the offending location of the overlay.
+    pub fn apply(&mut self, overlay: &Overlay) -> Result<Vec<Change>, GrammarError> {
+        let mut grammar = self.clone();
+        let mut changes = Vec::new();
+
+        for (index, change) in overlay.patterns.iter().enumerate() {
+            let path = format!("patterns[{}]", index);
+            grammar.check_classes(change, &path)?;
+
+            let replaced = |field| Change {
+                kind: ChangeKind::Replaced,
+                find: change.find.clone(),
+                field: Some(field),
+            };
+
+            match grammar.patterns.iter_mut().find(|pattern| pattern.find == change.find) {
+                Some(pattern) => {
+                    if let Some(replace) = &change.replace {
+                        pattern.replace = replace.clone();
+                        changes.push(replaced("replace"));
+                    }
+                    if let Some(alternatives) = &change.alternatives {
+                        pattern.alternatives = alternatives.clone();
+                        changes.push(replaced("alternatives"));
+                    }
+                    if let Some(rules) = &change.rules {
+                        pattern.rules = rules.clone();
+                        changes.push(replaced("rules"));
+                    }
+                    if !change.add_rules.is_empty() {
+                        pattern.rules.splice(0..0, change.add_rules.iter().cloned());
+                        changes.push(Change {
+                            kind: ChangeKind::RulesAdded,
+                            find: change.find.clone(),
+                            field: None,
+                        });
+                    }
+                }
+                None => {
+                    if change.find.is_empty() {
+                        return Err(GrammarError::EmptyFind { path: format!("{}.find", path) });
+                    }
+                    let replace = change
+                        .replace
+                        .clone()
+                        .ok_or_else(|| GrammarError::MissingField { path: format!("{}.replace", path) })?;
+
+                    let mut rules = change.add_rules.clone();
+                    rules.extend(change.rules.iter().flatten().cloned());
+                    grammar.patterns.push(Pattern {
+                        find: change.find.clone(),
+                        replace,
+                        alternatives: change.alternatives.clone().unwrap_or_default(),
+                        rules,
+                    });
+                    changes.push(Change {
+                        kind: ChangeKind::Added,
+                        find: change.find.clone(),
+                        field: None,
+                    });
+                }
+            }
+        }
+
+        for (index, find) in overlay.remove.iter().enumerate() {
+            let count = grammar.patterns.len();
+            grammar.patterns.retain(|pattern| &pattern.find != find);
+            if grammar.patterns.len() == count {
+                return Err(GrammarError::UnknownPattern {
+                    path: format!("remove[{}]", index),
+                    find: find.clone(),
+                });
+            }
+            changes.push(Change {
+                kind: ChangeKind::Removed,
+                find: find.clone(),
+                field: None,
+            });
+        }
+
+        *self = grammar;
+        Ok(changes)
+    }
+
+    /// Checks that the rules of a pattern change only use classes of the grammar.
+    fn check_classes(&self, change: &PatternChange, path: &str) -> Result<(), GrammarError> {
+        if let Some(rules) = &change.rules {
+            self.check_rules(rules, &format!("{}.rules", path))?;
+        }
+        self.check_rules(&change.add_rules, &format!("{}.addrules", path))
+    }
+}
+
+impl PhoneticParser {
+    /// Creates a parser from a base grammar with the overlays applied
+    /// in order, along with the changes made by each overlay.
+    pub fn with_overlays(mut base: Grammar, overlays: &[Overlay]) -> Result<(PhoneticParser, Vec<Vec<Change>>), GrammarError> {
+        let reports = overlays
+            .iter()
+            .map(|overlay| base.apply(overlay))
+            .collect::<Result<_, _>>()?;
+        Ok((PhoneticParser::from_grammar(base), reports))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avro::AvroPhonetic;
+    use crate::grammar::{Match, MatchType, Scope};
+    use crate::parser::PhoneticParser;
+    use super::*;
+
+    fn rule(replace: &str) -> Rule {
+        Rule {
+            matches: vec![Match { kind: MatchType::Prefix, scope: Scope::Vowel, negative: false }],
+            replace: replace.to_string(),
+            alternatives: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let avro = AvroPhonetic::new();
+        let mut grammar = avro.grammar();
+
+        let overlay = Overlay {
+            patterns: vec![
+                PatternChange { find: "x".to_string(), add_rules: vec![rule("ক্স")], ..Default::default() },
+                PatternChange {
+                    find: "rZ".to_string(),
+                    replace: Some("র্য".to_string()),
+                    rules: Some(Vec::new()),
+                    ..Default::default()
+                },
+                PatternChange { find: "qq".to_string(), replace: Some("ক্ক".to_string()), ..Default::default() },
+            ],
+            remove: vec!["Sc".to_string()],
+        };
+        let changes = grammar.apply(&overlay).unwrap();
+        let report: Vec<String> = changes.iter().map(Change::to_string).collect();
+        assert_eq!(report, vec![
+            "`x`: added rules",
+            "`rZ`: replaced `replace`",
+            "`rZ`: replaced `rules`",
+            "`qq`: added",
+            "`Sc`: removed",
+        ]);
+
+        let parser = PhoneticParser::from_grammar(grammar);
+        assert_eq!(parser.convert("ax"), "আক্স");
+        assert_eq!(parser.convert("x"), avro.convert("x"));
+        assert_eq!(parser.convert("krZ"), "কর্য");
+        assert_eq!(parser.convert("qq"), "ক্ক");
+        assert_eq!(parser.convert("Sc"), avro.convert("S") + &avro.convert("c"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut grammar = AvroPhonetic::new().grammar();
+        let original = grammar.clone();
+
+        let overlay = Overlay {
+            patterns: vec![PatternChange { find: "w".to_string(), replace: Some("ও".to_string()), ..Default::default() }],
+            remove: vec!["qq".to_string()],
+        };
+        let err = grammar.apply(&overlay).unwrap_err();
+        assert_eq!(err.to_string(), "remove[0]: no pattern `qq` in the grammar");
+        assert_eq!(grammar, original);
+
+        let overlay = Overlay {
+            patterns: vec![PatternChange { find: "qq".to_string(), ..Default::default() }],
+            remove: Vec::new(),
+        };
+        let err = grammar.apply(&overlay).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[0].replace: missing field");
+
+        let mut unknown = rule("");
+        unknown.matches[0].scope = Scope::Class("aspirate".to_string());
+        let overlay = Overlay {
+            patterns: vec![PatternChange { find: "w".to_string(), add_rules: vec![unknown], ..Default::default() }],
+            remove: Vec::new(),
+        };
+        let err = grammar.apply(&overlay).unwrap_err();
+        assert_eq!(err.to_string(), "patterns[0].addrules[0].matches[0].scope: unknown scope `aspirate`");
+    }
+}
@@ -1,225 +1,527 @@
-#![allow(unused_assignments)]
-use std::cmp::Ordering;
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::mem;
+use std::sync::Arc;
+#[cfg(feature = "json")]
 use serde_json::Value;
-use stringplus::StringPlus;
+use crate::grammar::{Escape, Grammar, GrammarError, Match, MatchType, Pattern, Rule, Scope};
+use crate::trie::Trie;
 
 /// Parses and converts text into Bengali according to given grammar.
+///
+/// Cloning a `PhoneticParser` is cheap, as clones share the same
+/// compiled grammar. It can be shared between threads.
+#[derive(Clone)]
 pub struct PhoneticParser {
-    patterns: Vec<Value>,
-    vowel: String,
-    consonant: String,
-    numbers: String,
-    case_sensitive: String,
+    compiled: Arc<Compiled>,
+}
+
+/// A grammar prepared for conversion.
+struct Compiled {
+    grammar: Grammar,
+    trie: Trie,
     max_pattern_len: usize,
+    /// How many bytes from its start a step may look at to decide
+    /// what it converts, see [`PhoneticParser::lookahead`].
+    lookahead: usize,
+}
+
+/// One step of the conversion: the chunk `fixed[start..end]`
+/// is replaced by `output`, or less likely by one of the `alternatives`.
+pub(crate) struct Step<'a> {
+    pub(crate) end: usize,
+    pub(crate) output: Cow<'a, str>,
+    pub(crate) alternatives: &'a [String],
+    /// The matched pattern, `None` if the chunk is copied or an escape.
+    pub(crate) pattern: Option<&'a Pattern>,
+    /// The index of the applied rule, `None` for the default `replace`.
+    pub(crate) rule: Option<usize>,
 }
 
+/// The longest UTF-8 encoded character in bytes.
+const MAX_CHAR_LEN: usize = 4;
+
+/// The most hexadecimal digits of a code point escape.
+const MAX_CODE_POINT_DIGITS: usize = 6;
+
 impl PhoneticParser {
     /// Creates a new `PhoneticParser` instance from the given Json
     /// value. The Json value must need to be a Json Object containing
     /// the required values, otherwise a panic would occur.
+    ///
+    /// Use [`try_new`](#method.try_new) to handle malformed grammars.
+    #[cfg(feature = "json")]
     pub fn new(rule: &Value) -> PhoneticParser {
+        match PhoneticParser::try_new(rule) {
+            Ok(parser) => parser,
+            Err(err) => panic!("Invalid grammar: {}", err),
+        }
+    }
+
+    /// Creates a new `PhoneticParser` instance from the given Json
+    /// value, returning an error describing where the grammar is malformed.
+    ///
+    /// A successfully created parser never panics while converting.
+    #[cfg(feature = "json")]
+    pub fn try_new(rule: &Value) -> Result<PhoneticParser, GrammarError> {
+        Grammar::from_value(rule).map(PhoneticParser::from_grammar)
+    }
+
+    /// Creates a new `PhoneticParser` instance from an already loaded grammar.
+    ///
+    /// The patterns of the grammar may be given in any order. If more than
+    /// one pattern has the same `find`, the first one is used. Escapes with
+    /// an empty opening delimiter are ignored; use
+    /// [`try_from_grammar`](#method.try_from_grammar) to reject them.
+    pub fn from_grammar(mut grammar: Grammar) -> PhoneticParser {
+        sort_patterns(&mut grammar.patterns);
+
+        let trie = Trie::new(grammar.patterns.iter().enumerate().map(|(i, p)| (p.find.as_str(), i)));
+        let max_pattern_len = grammar.patterns.iter().map(|p| p.find.len()).max().unwrap_or(0);
+        let lookahead = grammar
+            .patterns
+            .iter()
+            .map(|pattern| pattern.find.len() + suffix_len(pattern))
+            .chain(grammar.verbatim.iter().map(|escape| escape.open.len()))
+            .chain(grammar.code_point.iter().map(|escape| {
+                escape.open.len() + MAX_CODE_POINT_DIGITS + escape.close.len()
+            }))
+            .fold(max_pattern_len.max(MAX_CHAR_LEN), usize::max);
+
         PhoneticParser {
-            patterns: rule["patterns"].as_array().unwrap().clone(),
-            vowel: rule["vowel"].as_str().unwrap().to_string(),
-            consonant: rule["consonant"].as_str().unwrap().to_string(),
-            numbers: rule["number"].as_str().unwrap().to_string(),
-            case_sensitive: rule["casesensitive"].as_str().unwrap().to_string(),
-            max_pattern_len: rule["patterns"][0]["find"].as_str().unwrap().len(),
+            compiled: Arc::new(Compiled {
+                grammar,
+                trie,
+                max_pattern_len,
+                lookahead,
+            }),
         }
     }
 
+    /// Creates a new `PhoneticParser` instance from a grammar which was built
+    /// or changed in code, after checking it with
+    /// [`Grammar::validate`](../grammar/struct.Grammar.html#method.validate).
+    pub fn try_from_grammar(grammar: Grammar) -> Result<PhoneticParser, GrammarError> {
+        grammar.validate()?;
+        Ok(PhoneticParser::from_grammar(grammar))
+    }
+
+    /// Returns the length of the longest `find` of the grammar in bytes.
+    pub fn max_pattern_len(&self) -> usize {
+        self.compiled.max_pattern_len
+    }
+
+    /// Returns how many bytes of the (case normalized) input, counted from
+    /// the start of a step, can influence that step. Prefix rules only look
+    /// backwards, so changing the input from byte `p` onwards leaves every
+    /// step starting at or before `p - lookahead` and ending before `p`
+    /// unchanged. A verbatim escape is the only step which can be longer
+    /// than the lookahead; without a closing delimiter, it reaches the end
+    /// of the input.
+    pub(crate) fn lookahead(&self) -> usize {
+        self.compiled.lookahead
+    }
+
+    /// Returns the grammar, with its patterns in the canonical order.
+    pub fn grammar(&self) -> &Grammar {
+        &self.compiled.grammar
+    }
+
+    /// Returns the pattern with the given `find`, if there is one.
+    pub fn pattern(&self, find: &str) -> Option<&Pattern> {
+        self.position(find).map(|index| &self.compiled.grammar.patterns[index])
+    }
+
+    /// Adds a pattern, replacing the pattern with the same `find`,
+    /// which is returned.
+    ///
+    /// Editing a parser rebuilds its prepared grammar. Clones of the
+    /// parser made before the edit keep the grammar they had.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rupantor::avro::AvroPhonetic;
+    /// use rupantor::grammar::Pattern;
+    ///
+    /// let mut parser = AvroPhonetic::new().parser();
+    /// parser.add_pattern(Pattern {
+    ///     find: "qq".to_string(),
+    ///     replace: "ক্ক".to_string(),
+    ///     alternatives: Vec::new(),
+    ///     rules: Vec::new(),
+    /// }).unwrap();
+    /// assert_eq!(parser.convert("aqq"), "আক্ক");
+    /// ```
+    pub fn add_pattern(&mut self, pattern: Pattern) -> Result<Option<Pattern>, GrammarError> {
+        if pattern.find.is_empty() {
+            return Err(GrammarError::EmptyFind { path: "find".to_string() });
+        }
+        self.grammar().check_rules(&pattern.rules, "rules")?;
+
+        let mut grammar = self.grammar().clone();
+        let replaced = match self.position(&pattern.find) {
+            Some(index) => Some(mem::replace(&mut grammar.patterns[index], pattern)),
+            None => {
+                grammar.patterns.push(pattern);
+                None
+            }
+        };
+        *self = PhoneticParser::from_grammar(grammar);
+        Ok(replaced)
+    }
+
+    /// Removes the pattern with the given `find` and returns it.
+    pub fn remove_pattern(&mut self, find: &str) -> Option<Pattern> {
+        let index = self.position(find)?;
+        let mut grammar = self.grammar().clone();
+        let removed = grammar.patterns.remove(index);
+        *self = PhoneticParser::from_grammar(grammar);
+        Some(removed)
+    }
+
+    /// Replaces the rules of the pattern with the given `find`
+    /// and returns its previous rules.
+    pub fn replace_rules(&mut self, find: &str, rules: Vec<Rule>) -> Result<Vec<Rule>, GrammarError> {
+        let index = self.position(find).ok_or_else(|| GrammarError::UnknownPattern {
+            path: String::new(),
+            find: find.to_string(),
+        })?;
+        self.grammar().check_rules(&rules, "rules")?;
+
+        let mut grammar = self.grammar().clone();
+        let replaced = mem::replace(&mut grammar.patterns[index].rules, rules);
+        *self = PhoneticParser::from_grammar(grammar);
+        Ok(replaced)
+    }
+
+    /// Returns the index of the pattern with the given `find`.
+    fn position(&self, find: &str) -> Option<usize> {
+        self.compiled
+            .grammar
+            .patterns
+            .binary_search_by(|pattern| pattern_order(&pattern.find, find))
+            .ok()
+    }
+
+    pub(crate) fn fix_char(&self, character: char) -> char {
+        self.compiled.grammar.fix_char(character)
+    }
+
     /// Converts the given input string into Bengali according to the grammar.
+    ///
+    /// Any UTF-8 input is accepted. Characters which are not matched by
+    /// any pattern are copied into the output unchanged.
+    ///
+    /// Text between the delimiters of a `verbatim` escape of the grammar is
+    /// copied into the output as it is, without the delimiters. Without a
+    /// closing delimiter, the rest of the input is copied. The hexadecimal
+    /// digits of a `codepoint` escape are replaced by the character with that
+    /// code point. Escapes are recognized where a new pattern could start,
+    /// and the rules around them see their delimiters.
     pub fn convert(&self, input: &str) -> String {
         let fixed = self.fix_string(input);
         let len = fixed.len();
         let mut output = String::with_capacity(len * 3);
 
+        // `cur` is a byte offset which always lies on a character boundary.
         let mut cur = 0;
         while cur < len {
-            let start = cur as i32;
-            let mut end: i32 = 0;
-            let mut matched = false;
-
-            for chunk_len in (1..=self.max_pattern_len).rev() {
-                end = start + chunk_len as i32;
-                if end <= len as i32 {
-                    let chunk = fixed.substring(start as usize, chunk_len as usize);
-
-                    // Binary Search
-                    let mut left: i32 = 0;
-                    let mut right = self.patterns.len() as i32 - 1;
-                    let mut mid: i32 = 0;
-                    while right >= left {
-                        mid = (right + left) / 2;
-                        let pattern = &self.patterns[mid as usize];
-                        let find = pattern["find"].as_str().unwrap();
-                        if find == chunk {
-                            let rules = pattern["rules"].as_array().unwrap();
-                            if !rules.is_empty() {
-                                for rule in rules {
-                                    let mut replace = true;
-                                    let mut chk = 0;
-                                    let matches = rule["matches"].as_array().unwrap();
-                                    for _match in matches {
-                                        let value = _match["value"].as_str().unwrap_or_default();
-                                        let _type = _match["type"].as_str().unwrap();
-                                        let mut scope = _match["scope"].as_str().unwrap();
-                                        let mut is_negative = false;
-
-                                        // Handle Negative
-                                        if &scope[0..1] == "!" {
-                                            is_negative = true;
-                                            scope = &scope[1..];
-                                        }
-
-                                        if _type == "suffix" {
-                                            chk = end;
-                                        } else {
-                                            chk = start - 1;
-                                        }
-
-                                        // Beginning
-                                        match scope {
-                                            "punctuation" => if ((chk < 0 && (_type == "prefix"))
-                                                || (chk >= len as i32 && (_type == "suffix"))
-                                                || self.is_punctuation(fixed.at(chk as usize)))
-                                                == is_negative
-                                            {
-                                                replace = false;
-                                                break;
-                                            },
-                                            "vowel" => if (((chk >= 0 && (_type == "prefix"))
-                                                || (chk < len as i32 && (_type == "suffix")))
-                                                && self.is_vowel(fixed.at(chk as usize)))
-                                                == is_negative
-                                            {
-                                                replace = false;
-                                                break;
-                                            },
-
-                                            "consonant" => if (((chk >= 0 && (_type == "prefix"))
-                                                || (chk < len as i32 && (_type == "suffix")))
-                                                && self.is_consonant(fixed.at(chk as usize)))
-                                                == is_negative
-                                            {
-                                                replace = false;
-                                                break;
-                                            },
-
-                                            "number" => if (((chk >= 0 && (_type == "prefix"))
-                                                || (chk < len as i32 && (_type == "suffix")))
-                                                && self.is_number(fixed.at(chk as usize)))
-                                                == is_negative
-                                            {
-                                                replace = false;
-                                                break;
-                                            },
-
-                                            "exact" => {
-                                                let mut s: i32 = 0;
-                                                let mut e: i32 = 0;
-                                                if _type == "suffix" {
-                                                    s = end;
-                                                    e = end + value.len() as i32;
-                                                } else {
-                                                    // Prefix
-                                                    s = start - value.len() as i32;
-                                                    e = start;
-                                                }
-                                                if !self.is_exact(value, &fixed, s, e, is_negative)
-                                                {
-                                                    replace = false;
-                                                    break;
-                                                }
-                                            }
-                                            _ => panic!("Unknown scope"),
-                                        };
-                                    }
-
-                                    if replace {
-                                        output += rule["replace"].as_str().unwrap();
-                                        cur = (end - 1) as usize;
-                                        matched = true;
-                                        break;
-                                    }
-                                }
-                            }
-
-                            if matched {
-                                break;
-                            }
-
-                            // Default
-                            output += pattern["replace"].as_str().unwrap();
-                            cur = (end - 1) as usize;
-                            matched = true;
-                            break;
-                        } else if find.len() > chunk.len()
-                            || (find.len() == chunk.len() && find.cmp(&chunk) == Ordering::Less)
-                        {
-                            left = mid + 1;
-                        } else {
-                            right = mid - 1;
-                        }
-                    }
-                    if matched {
-                        break;
+            let step = self.step(input, &fixed, cur);
+            output += &step.output;
+            cur = step.end;
+        }
+
+        output
+    }
+
+    /// Converts the given input string into at most `limit` candidate
+    /// outputs, the most plausible first.
+    ///
+    /// The first candidate is the output of [`convert`](#method.convert).
+    /// The others replace chunks of it with the `alternatives` of the
+    /// applied patterns and rules; candidates using fewer and more preferred
+    /// alternatives come first. Duplicate outputs are only returned once.
+    pub fn convert_candidates(&self, input: &str, limit: usize) -> Vec<String> {
+        let fixed = self.fix_string(input);
+        let mut steps = Vec::new();
+        let mut cur = 0;
+        while cur < fixed.len() {
+            let step = self.step(input, &fixed, cur);
+            cur = step.end;
+            steps.push(step);
+        }
+
+        // Only the steps with alternatives can vary.
+        let choices: Vec<usize> = (0..steps.len())
+            .filter(|&index| !steps[index].alternatives.is_empty())
+            .collect();
+
+        // Enumerate the choices by their cost, the sum of the chosen
+        // alternative positions. Every choice is only reached once, by
+        // raising the positions from left to right.
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((0, vec![0; choices.len()], 0)));
+        let mut seen = HashSet::new();
+        let mut candidates = Vec::new();
+
+        while let Some(Reverse((cost, chosen, first))) = queue.pop() {
+            if candidates.len() == limit {
+                break;
+            }
+
+            let mut output = String::with_capacity(fixed.len() * 3);
+            let mut next = chosen.iter().zip(&choices).peekable();
+            for (index, step) in steps.iter().enumerate() {
+                match next.peek() {
+                    Some(&(&position, &choice)) if choice == index => {
+                        next.next();
+                        output += match position {
+                            0 => &step.output,
+                            _ => &step.alternatives[position - 1],
+                        };
                     }
+                    _ => output += &step.output,
                 }
             }
+            if seen.insert(output.clone()) {
+                candidates.push(output);
+            }
 
-            if !matched {
-                output += &fixed[cur..cur + 1];
+            for choice in first..choices.len() {
+                if chosen[choice] < steps[choices[choice]].alternatives.len() {
+                    let mut raised = chosen.clone();
+                    raised[choice] += 1;
+                    queue.push(Reverse((cost + 1, raised, choice)));
+                }
             }
-            cur += 1;
         }
 
-        output
+        candidates
     }
 
-    fn fix_string(&self, string: &str) -> String {
-        string
-            .chars()
-            .map(|character| {
-                if self.is_case_sensitive(character) {
-                    character
-                } else {
-                    character.to_ascii_lowercase()
+    /// Converts the chunk starting at byte `start` of the case normalized
+    /// input `fixed`, which must be a character boundary. `input` is the
+    /// input before case normalization.
+    pub(crate) fn step<'a>(&'a self, input: &'a str, fixed: &'a str, start: usize) -> Step<'a> {
+        if let Some(step) = self.escape(input, start) {
+            return step;
+        }
+
+        match self.compiled.trie.longest_match(&fixed.as_bytes()[start..]) {
+            Some((index, chunk_len)) => {
+                let pattern = &self.compiled.grammar.patterns[index];
+                let end = start + chunk_len;
+                let rule = pattern
+                    .rules
+                    .iter()
+                    .position(|rule| self.rule_applies(rule, fixed, start, end));
+                let (output, alternatives) = match rule {
+                    Some(index) => (&pattern.rules[index].replace, &pattern.rules[index].alternatives),
+                    None => (&pattern.replace, &pattern.alternatives),
+                };
+
+                Step {
+                    end,
+                    output: Cow::Borrowed(output),
+                    alternatives,
+                    pattern: Some(pattern),
+                    rule,
+                }
+            }
+            None => {
+                let width = fixed[start..].chars().next().map_or(1, char::len_utf8);
+                Step {
+                    end: start + width,
+                    output: Cow::Borrowed(&fixed[start..start + width]),
+                    alternatives: &[],
+                    pattern: None,
+                    rule: None,
                 }
+            }
+        }
+    }
+
+    /// Converts the escape starting at byte `start` of the input, if any.
+    pub(crate) fn escape<'a>(&'a self, input: &'a str, start: usize) -> Option<Step<'a>> {
+        let rest = &input[start..];
+        let grammar = &self.compiled.grammar;
+
+        // An empty opening delimiter would be found everywhere without consuming anything.
+        let code_point = grammar
+            .code_point
+            .as_ref()
+            .filter(|escape| !escape.open.is_empty())
+            .and_then(|escape| code_point(rest, escape));
+        if let Some((character, len)) = code_point {
+            return Some(Step {
+                end: start + len,
+                output: Cow::Owned(character.to_string()),
+                alternatives: &[],
+                pattern: None,
+                rule: None,
+            });
+        }
+
+        grammar.verbatim.iter().filter(|escape| !escape.open.is_empty()).find_map(|escape| {
+            let text = rest.strip_prefix(escape.open.as_str())?;
+            let (output, len) = match text.find(escape.close.as_str()) {
+                Some(end) => (&text[..end], escape.open.len() + end + escape.close.len()),
+                None => (text, rest.len()),
+            };
+            Some(Step {
+                end: start + len,
+                output: Cow::Borrowed(output),
+                alternatives: &[],
+                pattern: None,
+                rule: None,
             })
-            .collect()
+        })
     }
 
-    fn is_vowel(&self, string: &str) -> bool {
-        self.vowel.contains(&string.to_ascii_lowercase())
+    /// Checks whether all of the `matches` of the `rule` are satisfied
+    /// for the chunk `fixed[start..end]`.
+    fn rule_applies(&self, rule: &Rule, fixed: &str, start: usize, end: usize) -> bool {
+        rule.matches
+            .iter()
+            .all(|_match| self.match_satisfied(_match, fixed, start, end))
     }
 
-    fn is_consonant(&self, string: &str) -> bool {
-        self.consonant.contains(&string.to_ascii_lowercase())
+    pub(crate) fn match_satisfied(&self, _match: &Match, fixed: &str, start: usize, end: usize) -> bool {
+        let (before, after) = (&fixed[..start], &fixed[end..]);
+
+        // The character next to the chunk, `None` at the beginning/end of the input.
+        let neighbour = match _match.kind {
+            MatchType::Prefix => before.chars().next_back(),
+            MatchType::Suffix => after.chars().next(),
+        };
+
+        let exact = |value: &str| match _match.kind {
+            MatchType::Prefix => before.ends_with(value),
+            // As in the original Avro parser, a suffix must not
+            // reach the end of the input.
+            MatchType::Suffix => after.starts_with(value) && value.len() < after.len(),
+        };
+
+        let satisfied = match &_match.scope {
+            Scope::Punctuation => neighbour.is_none_or(|c| self.is_punctuation(c)),
+            Scope::Vowel => neighbour.is_some_and(|c| self.is_vowel(c)),
+            Scope::Consonant => neighbour.is_some_and(|c| self.is_consonant(c)),
+            Scope::Number => neighbour.is_some_and(|c| self.is_number(c)),
+            Scope::Whitespace => neighbour.is_some_and(char::is_whitespace),
+            Scope::Start => before.is_empty(),
+            Scope::End => after.is_empty(),
+            Scope::Class(name) => {
+                let grammar = &self.compiled.grammar;
+                let class = grammar.classes.get(name);
+                neighbour.is_some_and(|c| class.is_some_and(|class| class.chars().any(|k| grammar.fix_char(k) == c)))
+            }
+            Scope::Exact(value) => exact(value),
+            Scope::ExactAny(values) => values.iter().any(|value| exact(value)),
+        };
+
+        satisfied != _match.negative
     }
 
-    fn is_case_sensitive(&self, character: char) -> bool {
-        self.case_sensitive
-            .contains(character.to_ascii_lowercase())
+    pub(crate) fn fix_string(&self, string: &str) -> String {
+        string
+            .chars()
+            .map(|character| self.compiled.grammar.fix_char(character))
+            .collect()
+    }
+
+    fn is_vowel(&self, character: char) -> bool {
+        self.compiled.grammar.vowel.contains(character.to_ascii_lowercase())
     }
 
-    fn is_number(&self, character: &str) -> bool {
-        self.numbers.contains(character)
+    fn is_consonant(&self, character: char) -> bool {
+        self.compiled.grammar.consonant.contains(character.to_ascii_lowercase())
     }
 
-    fn is_exact(&self, needle: &str, heystack: &str, start: i32, end: i32, not: bool) -> bool {
-        let len = end - start;
-        (start >= 0 && end < heystack.len() as i32
-            && (heystack.substring(start as usize, len as usize) == needle)) != not
+    fn is_number(&self, character: char) -> bool {
+        self.compiled.grammar.number.contains(character)
     }
 
-    fn is_punctuation(&self, character: &str) -> bool {
-        !(self.is_vowel(character) || self.is_consonant(character))
+    pub(crate) fn is_punctuation(&self, character: char) -> bool {
+        let grammar = &self.compiled.grammar;
+        let punctuation = match &grammar.punctuation {
+            Some(punctuation) => punctuation.contains(character),
+            None => !(self.is_vowel(character) || self.is_consonant(character)),
+        };
+        punctuation && (grammar.number_boundary || !self.is_number(character))
     }
 }
 
+/// Returns how many bytes after the matched text the rules of
+/// the pattern may look at.
+fn suffix_len(pattern: &Pattern) -> usize {
+    pattern
+        .rules
+        .iter()
+        .flat_map(|rule| &rule.matches)
+        // An `end` prefix, rejected by validation, still looks after the matched text.
+        .filter(|_match| _match.kind == MatchType::Suffix || _match.scope == Scope::End)
+        .map(|_match| match &_match.scope {
+            // `exact` also checks that the input continues after the value.
+            Scope::Exact(value) => value.len() + 1,
+            Scope::ExactAny(values) => values.iter().map(|value| value.len() + 1).max().unwrap_or(0),
+            _ => MAX_CHAR_LEN,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Parses a code point escape at the start of `text`, returning
+/// the character and the length of the escape.
+fn code_point(text: &str, escape: &Escape) -> Option<(char, usize)> {
+    let digits = text.strip_prefix(escape.open.as_str())?;
+    let len = digits.bytes().take_while(u8::is_ascii_hexdigit).count();
+    if len == 0 || len > MAX_CODE_POINT_DIGITS || !digits[len..].starts_with(escape.close.as_str()) {
+        return None;
+    }
+
+    let character = u32::from_str_radix(&digits[..len], 16).ok().and_then(char::from_u32)?;
+    Some((character, escape.open.len() + len + escape.close.len()))
+}
+
+/// Puts the patterns into the canonical order and drops the
+/// later ones of the patterns with the same `find`.
+pub(crate) fn sort_patterns(patterns: &mut Vec<Pattern>) {
+    patterns.sort_by(|a, b| pattern_order(&a.find, &b.find));
+    patterns.dedup_by(|later, first| later.find == first.find);
+}
+
+/// The order of patterns: longer ones first, then lexically.
+fn pattern_order(a: &str, b: &str) -> Ordering {
+    b.len().cmp(&a.len()).then_with(|| a.cmp(b))
+}
+
+/// Extends a grammar with every setting and kind of match which Avro Phonetic
+/// does not use, for the round trip tests of the grammar formats.
 #[cfg(test)]
-mod tests {
+pub(crate) fn extended(mut grammar: Grammar) -> Grammar {
+    grammar.classes.insert("aspirate".to_string(), "h".to_string());
+    grammar.punctuation = Some(" .,\"".to_string());
+    grammar.number_boundary = false;
+    grammar.patterns[0].rules.push(Rule {
+        matches: vec![
+            Match { kind: MatchType::Suffix, scope: Scope::Class("aspirate".to_string()), negative: true },
+            Match { kind: MatchType::Prefix, scope: Scope::ExactAny(vec!["k".to_string(), "->".to_string()]), negative: false },
+            Match { kind: MatchType::Prefix, scope: Scope::Exact("->".to_string()), negative: true },
+            Match { kind: MatchType::Prefix, scope: Scope::Whitespace, negative: false },
+            Match { kind: MatchType::Prefix, scope: Scope::Start, negative: true },
+        ],
+        replace: "\t\"".to_string(),
+        alternatives: vec![String::new(), "\u{200c}".to_string()],
+    });
+    grammar.verbatim.push(Escape { open: "{".to_string(), close: "}".to_string() });
+    grammar.verbatim.push(Escape { open: "[[".to_string(), close: "]]".to_string() });
+    grammar.code_point = Some(Escape { open: "\\u{".to_string(), close: "}".to_string() });
+    grammar
+}
+
+#[cfg(all(test, feature = "json"))]
+pub(crate) mod tests {
     use serde_json;
     use super::PhoneticParser;
 
@@ -228,19 +530,22 @@ mod tests {
         let json = serde_json::from_str(include_str!("AvroPhonetic.json")).unwrap();
         let parser = PhoneticParser::new(&json);
 
-        assert!(parser.is_vowel("A"));
-        assert_eq!(parser.is_vowel("b"), false);
-        assert!(parser.is_consonant("B"));
-        assert_eq!(parser.is_consonant("e"), false);
+        assert!(parser.is_vowel('A'));
+        assert!(!parser.is_vowel('b'));
+        assert!(parser.is_consonant('B'));
+        assert!(!parser.is_consonant('e'));
         assert_eq!(parser.fix_string("ODEr AMAr"), "ODer amar");
-        assert!(parser.is_number("1"));
+        assert!(parser.is_number('1'));
     }
 
     #[test]
     fn test_basic() {
         let json = serde_json::from_str(include_str!("AvroPhonetic.json")).unwrap();
-        let parser = PhoneticParser::new(&json);
+        assert_avro(&PhoneticParser::new(&json));
+    }
 
+    /// Checks the conversions of a parser with the Avro Phonetic grammar.
+    pub(crate) fn assert_avro(parser: &PhoneticParser) {
         assert_eq!(parser.convert("bhl"), "ভ্ল");
         assert_eq!(parser.convert("bj"), "ব্জ");
         assert_eq!(parser.convert("bd"), "ব্দ");
@@ -654,6 +959,333 @@ mod tests {
         assert_eq!(parser.convert("bdh"), "ব্ধ");
     }
 
+    #[test]
+    fn test_try_new() {
+        let json = serde_json::json!({
+            "vowel": "aeiou",
+            "consonant": "bcdfghjklmnpqrstvwxyz",
+            "number": "1234567890",
+            "casesensitive": "",
+            "patterns": [{
+                "find": "a",
+                "replace": "আ",
+                "rules": [{ "matches": [{ "type": "prefix", "scope": "digit" }], "replace": "া" }]
+            }]
+        });
+        let err = PhoneticParser::try_new(&json).err().unwrap();
+        assert_eq!(err.to_string(), "patterns[0].rules[0].matches[0].scope: unknown scope `digit`");
+    }
+
+    #[test]
+    fn test_pattern_order() {
+        let mut json: serde_json::Value = serde_json::from_str(include_str!("AvroPhonetic.json")).unwrap();
+        let sorted = PhoneticParser::new(&json);
+
+        json["patterns"].as_array_mut().unwrap().reverse();
+        let reversed = PhoneticParser::new(&json);
+        assert_eq!(reversed.max_pattern_len(), 5);
+        assert_eq!(reversed.compiled.grammar.patterns, sorted.compiled.grammar.patterns);
+        assert_eq!(reversed.convert("ami banglay gan gai"), "আমি বাংলায় গান গাই");
+    }
+
+    #[test]
+    fn test_duplicate_find() {
+        let json = serde_json::json!({
+            "vowel": "aeiou",
+            "consonant": "bcdfghjklmnpqrstvwxyz",
+            "number": "1234567890",
+            "casesensitive": "",
+            "patterns": [
+                { "find": "k", "replace": "ক", "rules": [] },
+                { "find": "kh", "replace": "খ", "rules": [] },
+                { "find": "k", "replace": "ঁ", "rules": [] }
+            ]
+        });
+        let parser = PhoneticParser::new(&json);
+        assert_eq!(parser.max_pattern_len(), 2);
+        assert_eq!(parser.convert("kkh"), "কখ");
+    }
+
+    #[test]
+    fn test_class_case() {
+        let json = serde_json::json!({
+            "vowel": "aeiou",
+            "consonant": "bcdfghjklmnpqrstvwxyz",
+            "number": "1234567890",
+            "casesensitive": "t",
+            "classes": { "aspirate": "H", "dental": "t" },
+            "patterns": [
+                {
+                    "find": "k",
+                    "replace": "ক",
+                    "rules": [
+                        { "matches": [{ "type": "suffix", "scope": "aspirate" }], "replace": "খ" },
+                        { "matches": [{ "type": "suffix", "scope": "dental" }], "replace": "ক্" }
+                    ]
+                },
+                { "find": "h", "replace": "", "rules": [] },
+                { "find": "t", "replace": "ট", "rules": [] },
+                { "find": "T", "replace": "ঠ", "rules": [] }
+            ]
+        });
+        let parser = PhoneticParser::new(&json);
+
+        // Classes are compared after the case normalization of the input.
+        assert_eq!(parser.convert("kh"), "খ");
+        assert_eq!(parser.convert("kH"), "খ");
+        assert_eq!(parser.convert("kt"), "ক্ট");
+        assert_eq!(parser.convert("kT"), "কঠ");
+    }
+
+    #[test]
+    fn test_edit() {
+        use crate::grammar::{Match, MatchType, Pattern, Rule, Scope};
+
+        let json = serde_json::json!({
+            "vowel": "aeiou",
+            "consonant": "bcdfghjklmnpqrstvwxyz",
+            "number": "1234567890",
+            "casesensitive": "",
+            "classes": { "aspirate": "h" },
+            "patterns": [
+                { "find": "k", "replace": "ক", "rules": [] },
+                { "find": "kh", "replace": "খ", "rules": [] }
+            ]
+        });
+        let mut parser = PhoneticParser::new(&json);
+        let before = parser.clone();
+        let pattern = |find: &str, replace: &str| Pattern {
+            find: find.to_string(),
+            replace: replace.to_string(),
+            alternatives: Vec::new(),
+            rules: Vec::new(),
+        };
+
+        assert_eq!(parser.add_pattern(pattern("kkhh", "ক্ষ")).unwrap(), None);
+        assert_eq!(parser.max_pattern_len(), 4);
+        assert_eq!(parser.convert("kkhhk"), "ক্ষক");
+        assert_eq!(before.convert("kkhhk"), "কখhক");
+
+        let replaced = parser.add_pattern(pattern("k", "K")).unwrap();
+        assert_eq!(replaced.unwrap().replace, "ক");
+        assert_eq!(parser.pattern("k").unwrap().replace, "K");
+        assert_eq!(parser.convert("kkh"), "Kখ");
+
+        let rule = Rule {
+            matches: vec![Match { kind: MatchType::Suffix, scope: Scope::Class("aspirate".to_string()), negative: false }],
+            replace: "খ্".to_string(),
+            alternatives: Vec::new(),
+        };
+        assert_eq!(parser.replace_rules("kkhh", vec![rule.clone()]).unwrap(), Vec::new());
+        assert_eq!(parser.convert("kkhhh"), "খ্h");
+
+        assert_eq!(parser.remove_pattern("kkhh").unwrap().rules, vec![rule]);
+        assert_eq!(parser.remove_pattern("kkhh"), None);
+        assert_eq!(parser.max_pattern_len(), 2);
+        assert_eq!(parser.convert("kkhh"), "Kখh");
+
+        let err = parser.add_pattern(pattern("", "x")).unwrap_err();
+        assert_eq!(err.to_string(), "find: must not be empty");
+        let mut unknown = pattern("g", "গ");
+        unknown.rules.push(Rule {
+            matches: vec![Match { kind: MatchType::Prefix, scope: Scope::Class("digit".to_string()), negative: true }],
+            replace: String::new(),
+            alternatives: Vec::new(),
+        });
+        let err = parser.add_pattern(unknown.clone()).unwrap_err();
+        assert_eq!(err.to_string(), "rules[0].matches[0].scope: unknown scope `digit`");
+        let err = parser.replace_rules("k", unknown.rules).unwrap_err();
+        assert_eq!(err.to_string(), "rules[0].matches[0].scope: unknown scope `digit`");
+        let err = parser.replace_rules("g", Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "no pattern `g` in the grammar");
+        assert!(parser.pattern("g").is_none());
+    }
+
+    #[test]
+    fn test_candidates() {
+        let json = serde_json::json!({
+            "vowel": "aeiou",
+            "consonant": "bcdfghjklmnpqrstvwxyz",
+            "number": "1234567890",
+            "casesensitive": "",
+            "patterns": [
+                { "find": "s", "replace": "স", "alternatives": ["শ", "ষ"], "rules": [] },
+                { "find": "n", "replace": "ন", "alternatives": ["ণ", "ন"], "rules": [] },
+                {
+                    "find": "i",
+                    "replace": "ি",
+                    "alternatives": ["ী"],
+                    "rules": [{
+                        "matches": [{ "type": "prefix", "scope": "punctuation" }],
+                        "replace": "ই",
+                        "alternatives": ["ঈ"]
+                    }]
+                }
+            ]
+        });
+        let parser = PhoneticParser::new(&json);
+
+        assert_eq!(parser.convert_candidates("is", 10), vec!["ইস", "ইশ", "ঈস", "ইষ", "ঈশ", "ঈষ"]);
+        assert_eq!(parser.convert_candidates("si", 3), vec!["সি", "সী", "শি"]);
+        // The duplicated `ন` alternative is only returned once.
+        assert_eq!(parser.convert_candidates("n", 10), vec!["ন", "ণ"]);
+        assert_eq!(parser.convert_candidates("x", 10), vec!["x"]);
+        assert_eq!(parser.convert_candidates("si", 0), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_scopes() {
+        let json = serde_json::json!({
+            "vowel": "aeiou",
+            "consonant": "bcdfghjklmnpqrstvwxyz",
+            "number": "1234567890",
+            "casesensitive": "",
+            "classes": { "aspirate": "h" },
+            "patterns": [
+                {
+                    "find": "k",
+                    "replace": "ক",
+                    "rules": [
+                        { "matches": [{ "type": "suffix", "scope": "aspirate" }], "replace": "খ" },
+                        { "matches": [{ "type": "prefix", "scope": "start" }], "replace": "K" },
+                        { "matches": [{ "type": "suffix", "scope": "end" }], "replace": "ক্" },
+                        { "matches": [{ "type": "prefix", "scope": "whitespace" }], "replace": "_" },
+                        { "matches": [{ "type": "suffix", "scope": "exact", "value": ["ss", "tt"] }], "replace": "ক্ক" }
+                    ]
+                },
+                { "find": "h", "replace": "", "rules": [] }
+            ]
+        });
+        let parser = PhoneticParser::new(&json);
+
+        assert_eq!(parser.convert("kh"), "খ");
+        assert_eq!(parser.convert("ka"), "Ka");
+        assert_eq!(parser.convert("ak"), "aক্");
+        assert_eq!(parser.convert("a ka"), "a _a");
+        assert_eq!(parser.convert("akssa"), "aক্কssa");
+        assert_eq!(parser.convert("akttt"), "aক্কttt");
+        // Like any `exact` suffix, the value must not reach the end of the input.
+        assert_eq!(parser.convert("akss"), "aকss");
+        assert_eq!(parser.convert("aksa"), "aকsa");
+
+        let mut json = json;
+        json["classes"]["vowel"] = serde_json::json!("aeiou");
+        let err = PhoneticParser::try_new(&json).err().unwrap();
+        assert_eq!(err.to_string(), "classes.vowel: is the name of a built-in scope");
+    }
+
+    #[test]
+    fn test_punctuation() {
+        let mut json = serde_json::json!({
+            "vowel": "aeiou",
+            "consonant": "bcdfghjklmnpqrstvwxyz",
+            "number": "1234567890",
+            "casesensitive": "",
+            "patterns": [{
+                "find": "a",
+                "replace": "া",
+                "rules": [{ "matches": [{ "type": "prefix", "scope": "punctuation" }], "replace": "আ" }]
+            }]
+        });
+        let parser = PhoneticParser::new(&json);
+        assert_eq!(parser.convert("a 1a 😀a"), "আ 1আ 😀আ");
+
+        json["numberboundary"] = serde_json::json!(false);
+        let parser = PhoneticParser::new(&json);
+        assert_eq!(parser.convert("a 1a 😀a"), "আ 1া 😀আ");
+
+        json["numberboundary"] = serde_json::json!(true);
+        json["punctuation"] = serde_json::json!(" .1");
+        let parser = PhoneticParser::new(&json);
+        assert_eq!(parser.convert("a 1a 😀a .a"), "আ 1আ 😀া .আ");
+
+        json["numberboundary"] = serde_json::json!("no");
+        let err = PhoneticParser::try_new(&json).err().unwrap();
+        assert_eq!(err.to_string(), "numberboundary: expected a boolean");
+    }
+
+    #[test]
+    fn test_escapes() {
+        let mut json: serde_json::Value = serde_json::from_str(include_str!("AvroPhonetic.json")).unwrap();
+        let avro = PhoneticParser::new(&json);
+        json["verbatim"] = serde_json::json!([{ "open": "{", "close": "}" }, { "open": "<<", "close": ">>" }]);
+        json["codepoint"] = serde_json::json!({ "open": "\\u{", "close": "}" });
+        let parser = PhoneticParser::new(&json);
+
+        assert_eq!(parser.convert("ami {Rupantor} bhalobasi"), "আমি Rupantor ভালবাসি");
+        assert_eq!(parser.convert("<<C++>> o {Rust"), "C++ অ Rust");
+        assert_eq!(parser.convert("{}"), "");
+        // Rules see the delimiters around the escape.
+        assert_eq!(parser.convert("a{x}a"), "আxআ");
+        assert_eq!(parser.convert("\\u{9F3}100"), "৳১০০");
+        // Invalid code points are no escape, but their `{` starts a verbatim escape.
+        assert_eq!(parser.convert("\\u{110000}"), "\\উ110000");
+        assert_eq!(parser.convert("\\u{1234567}"), "\\উ1234567");
+        assert_eq!(parser.convert("\\u{}"), avro.convert("\\u"));
+    }
+
+    #[test]
+    fn test_empty_escape() {
+        use crate::grammar::{Escape, GrammarError, Match, MatchType, Scope};
+
+        let json = serde_json::from_str(include_str!("AvroPhonetic.json")).unwrap();
+        let avro = PhoneticParser::new(&json);
+        let mut grammar = avro.grammar().clone();
+        let empty = Escape { open: String::new(), close: String::new() };
+        grammar.verbatim.push(empty.clone());
+        grammar.code_point = Some(empty);
+
+        // Escapes with an empty opening delimiter are never found.
+        assert_eq!(PhoneticParser::from_grammar(grammar.clone()).convert("ami"), "আমি");
+        let err = PhoneticParser::try_from_grammar(grammar).err().unwrap();
+        assert_eq!(err.to_string(), "verbatim[0].open: must not be empty");
+
+        let mut grammar = avro.grammar().clone();
+        grammar.classes.insert("number".to_string(), "0".to_string());
+        let err = PhoneticParser::try_from_grammar(grammar).err().unwrap();
+        assert!(matches!(err, GrammarError::ReservedClass { .. }));
+
+        let mut grammar = avro.grammar().clone();
+        let index = grammar.patterns.iter().position(|pattern| pattern.find == "o").unwrap();
+        grammar.patterns[index].rules[0].matches[0].scope = Scope::Class("digit".to_string());
+        let err = PhoneticParser::try_from_grammar(grammar).err().unwrap();
+        assert_eq!(err.to_string(), format!("patterns[{}].rules[0].matches[0].scope: unknown scope `digit`", index));
+
+        let mut grammar = avro.grammar().clone();
+        grammar.patterns[index].rules[0].matches[0] = Match { kind: MatchType::Suffix, scope: Scope::Start, negative: false };
+        let err = PhoneticParser::try_from_grammar(grammar).err().unwrap();
+        assert_eq!(err.to_string(), format!("patterns[{}].rules[0].matches[0].type: `start` can only be a prefix", index));
+        assert!(PhoneticParser::try_from_grammar(avro.grammar().clone()).is_ok());
+    }
+
+    #[test]
+    fn test_unicode() {
+        let json = serde_json::from_str(include_str!("AvroPhonetic.json")).unwrap();
+        let parser = PhoneticParser::new(&json);
+
+        assert_eq!(parser.convert("ami বাংলা 😀 bhalo"), "আমি বাংলা 😀 ভাল");
+        assert_eq!(parser.convert("caféo"), "চাফéঅ");
+        assert_eq!(parser.convert("😀o"), "😀অ");
+        assert_eq!(parser.convert("o😀"), "অ😀");
+        assert_eq!(parser.convert("কo"), "কঅ");
+        assert_eq!(parser.convert("ÀBC"), "Àবচ");
+        assert_eq!(parser.convert("rZ😀"), "র‍্য😀");
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<PhoneticParser>();
+
+        let json = serde_json::from_str(include_str!("AvroPhonetic.json")).unwrap();
+        let parser = PhoneticParser::new(&json);
+        let clone = parser.clone();
+        assert!(std::sync::Arc::ptr_eq(&parser.compiled, &clone.compiled));
+
+        let handle = std::thread::spawn(move || clone.convert("bhalo"));
+        assert_eq!(handle.join().unwrap(), parser.convert("bhalo"));
+    }
+
     #[test]
     fn test_sentence() {
         let json = serde_json::from_str(include_str!("AvroPhonetic.json")).unwrap();
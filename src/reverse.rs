@@ -0,0 +1,380 @@
+//! Reverse transliteration: from Bengali back to the Roman input of a grammar.
+//!
+//! The reverse mapping is derived from the patterns of the grammar: every
+//! `replace` (of the pattern or of one of its rules) can be typed with the
+//! pattern's `find`. As the output of a pattern depends on its context, the
+//! candidates are combined by a search which verifies them with the forward
+//! [`PhoneticParser`](../parser/struct.PhoneticParser.html), so the produced
+//! Roman text converts back into the same Bengali text. The search of a word
+//! is limited, see [`ReverseParser::convert`](struct.ReverseParser.html#method.convert).
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use crate::parser::PhoneticParser;
+use crate::trie::Trie;
+
+/// How many search steps are spent on a single word before falling back
+/// to the first candidates. Words typed with the grammar need far less.
+const MAX_SEARCH_STEPS: usize = 10_000;
+
+/// Converts Bengali text into Roman text which the given
+/// grammar converts back into the same Bengali text.
+#[derive(Clone)]
+pub struct ReverseParser {
+    parser: PhoneticParser,
+    /// Bengali texts, indexing into `candidates`.
+    trie: Trie,
+    /// Roman texts for every Bengali text, preferred ones first.
+    candidates: Vec<Vec<String>>,
+    /// Roman texts which may produce no output at all, like the
+    /// `` ` `` separator of Avro Phonetic.
+    separators: Vec<String>,
+    /// Every character which appears in the output of a pattern.
+    produced: HashSet<char>,
+}
+
+/// How a Roman text converts compared to the Bengali text it should produce.
+#[derive(PartialEq)]
+enum Verdict {
+    /// It converts into the Bengali text.
+    Exact,
+    /// It could still convert into the Bengali text once more is typed.
+    Possible,
+    Impossible,
+}
+
+impl ReverseParser {
+    /// Creates a `ReverseParser` for the grammar of the given parser.
+    pub fn new(parser: PhoneticParser) -> ReverseParser {
+        let mut bengali: Vec<(String, String)> = Vec::new();
+        let mut separators = Vec::new();
+
+        for pattern in &parser.grammar().patterns {
+            // A `find` which changes its case can never be typed.
+            if pattern.find.chars().any(|c| parser.fix_char(c) != c) {
+                continue;
+            }
+            let outputs = Some(&pattern.replace)
+                .into_iter()
+                .chain(pattern.rules.iter().map(|rule| &rule.replace));
+            for output in outputs {
+                if output.is_empty() {
+                    separators.push(pattern.find.clone());
+                } else {
+                    bengali.push((output.clone(), pattern.find.clone()));
+                }
+            }
+        }
+
+        // Prefer Roman texts with less symbols and capital letters, then shorter ones.
+        let preference = |roman: &String| {
+            let symbols = roman.chars().filter(|c| !c.is_ascii_alphanumeric()).count();
+            let capitals = roman.chars().filter(char::is_ascii_uppercase).count();
+            (symbols, capitals, roman.len(), roman.clone())
+        };
+        bengali.sort_by_key(|(output, roman)| (output.clone(), preference(roman)));
+        bengali.dedup();
+        separators.sort_by_key(preference);
+        separators.dedup();
+
+        let mut candidates: Vec<Vec<String>> = Vec::new();
+        let mut texts: Vec<String> = Vec::new();
+        for (output, roman) in bengali {
+            if texts.last() != Some(&output) {
+                texts.push(output);
+                candidates.push(Vec::new());
+            }
+            candidates.last_mut().unwrap().push(roman);
+        }
+        let trie = Trie::new(texts.iter().enumerate().map(|(i, text)| (text.as_str(), i)));
+        let produced = texts.iter().flat_map(|text| text.chars()).collect();
+
+        ReverseParser {
+            parser,
+            trie,
+            candidates,
+            separators,
+            produced,
+        }
+    }
+
+    /// Converts the given Bengali text into Roman text.
+    ///
+    /// Text which the grammar can produce is converted into Roman text which
+    /// converts back into the same text. Characters which no pattern produces
+    /// are kept as they are.
+    ///
+    /// The search for a word only converts the Roman text typed since its
+    /// last stable step, and is limited to guard against pathological input.
+    /// If it gives up, the preferred candidates of the word are used
+    /// unverified, so the word may not convert back into the same Bengali text.
+    pub fn convert(&self, input: &str) -> String {
+        let mut output = String::with_capacity(input.len());
+        let mut rest = input;
+
+        // Words are converted one by one to keep the search small.
+        while !rest.is_empty() {
+            let word_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let (word, after) = rest.split_at(word_len);
+            output += &self.convert_word(word);
+
+            let space_len = after.find(|c: char| !c.is_whitespace()).unwrap_or(after.len());
+            output += &after[..space_len];
+            rest = &after[space_len..];
+        }
+
+        output
+    }
+
+    fn convert_word(&self, word: &str) -> String {
+        if let Some(roman) = self.search_word(word) {
+            return roman;
+        }
+
+        // Fall back to the preferred candidates.
+        let mut pos = 0;
+        let mut roman = String::new();
+        while pos < word.len() {
+            match self.candidates_at(word, pos).first() {
+                Some(&(part, len)) => {
+                    roman += part;
+                    pos += len;
+                }
+                None => {
+                    let len = word[pos..].chars().next().map_or(1, char::len_utf8);
+                    roman += &word[pos..pos + len];
+                    pos += len;
+                }
+            }
+        }
+        roman
+    }
+
+    /// Searches Roman text which converts into `word`, giving up
+    /// after `MAX_SEARCH_STEPS` steps.
+    fn search_word(&self, word: &str) -> Option<String> {
+        let mut typed = Typed::default();
+        let mut steps = 0;
+        let start = Checkpoint { roman: 0, output: 0 };
+        if self.search(word, 0, &mut typed, start, true, &mut steps) {
+            Some(typed.roman)
+        } else {
+            None
+        }
+    }
+
+    /// Searches Roman text for `target[pos..]` and appends it to `typed`,
+    /// which converts up to `checkpoint` as it will in the end.
+    fn search(
+        &self,
+        target: &str,
+        pos: usize,
+        typed: &mut Typed,
+        checkpoint: Checkpoint,
+        separated: bool,
+        steps: &mut usize,
+    ) -> bool {
+        if pos == target.len() {
+            return self.verdict(typed, target, checkpoint).0 == Verdict::Exact;
+        }
+        if *steps == MAX_SEARCH_STEPS {
+            return false;
+        }
+        *steps += 1;
+
+        // Separators are only tried when no candidate leads to a result.
+        let candidates = self.candidates_at(target, pos);
+        let separators: Vec<(&str, usize)> = if separated {
+            Vec::new()
+        } else {
+            self.separators.iter().map(|separator| (separator.as_str(), 0)).collect()
+        };
+
+        self.try_parts(candidates, target, pos, typed, checkpoint, steps)
+            || self.try_parts(separators, target, pos, typed, checkpoint, steps)
+    }
+
+    /// Tries to continue the search with each of the given parts, the ones
+    /// producing exactly the Bengali text so far first.
+    fn try_parts(
+        &self,
+        parts: Vec<(&str, usize)>,
+        target: &str,
+        pos: usize,
+        typed: &mut Typed,
+        checkpoint: Checkpoint,
+        steps: &mut usize,
+    ) -> bool {
+        let len = typed.roman.len();
+        let mut possible = Vec::new();
+        for (part, part_len) in parts {
+            typed.push(&self.parser, part);
+            match self.verdict(typed, &target[..pos + part_len], checkpoint) {
+                (Verdict::Exact, next) => {
+                    if self.search(target, pos + part_len, typed, next, part_len == 0, steps) {
+                        return true;
+                    }
+                }
+                (Verdict::Possible, next) => possible.push((part, part_len, next)),
+                (Verdict::Impossible, _) => {}
+            }
+            typed.truncate(len);
+        }
+
+        for (part, part_len, next) in possible {
+            typed.push(&self.parser, part);
+            if self.search(target, pos + part_len, typed, next, part_len == 0, steps) {
+                return true;
+            }
+            typed.truncate(len);
+        }
+
+        false
+    }
+
+    /// Returns the Roman candidates for the Bengali text at `pos`, along with
+    /// the length of the Bengali text they produce. Longer Bengali texts come
+    /// first. A character which no pattern produces is its own candidate.
+    fn candidates_at<'a>(&'a self, target: &'a str, pos: usize) -> Vec<(&'a str, usize)> {
+        let mut prefixes = self.trie.prefixes(&target.as_bytes()[pos..]);
+        prefixes.sort_by_key(|&(_, len)| Reverse(len));
+
+        let mut trials: Vec<(&str, usize)> = prefixes
+            .into_iter()
+            .flat_map(|(index, len)| self.candidates[index].iter().map(move |roman| (roman.as_str(), len)))
+            .collect();
+
+        if let Some(character) = target[pos..].chars().next() {
+            if !self.produced.contains(&character) {
+                let len = character.len_utf8();
+                trials.push((&target[pos..pos + len], len));
+            }
+        }
+        trials
+    }
+
+    /// Checks whether the typed text converts, or could still convert, into
+    /// `target`. Only the steps after `checkpoint` are converted. Returns the
+    /// verdict and the checkpoint for the text typed next.
+    fn verdict(&self, typed: &Typed, target: &str, checkpoint: Checkpoint) -> (Verdict, Checkpoint) {
+        let (roman, fixed) = (typed.roman.as_str(), typed.fixed.as_str());
+        let lookahead = self.parser.lookahead();
+
+        let mut output = String::new();
+        let mut stable = checkpoint;
+        let mut cur = checkpoint.roman;
+        while cur < fixed.len() {
+            let step = self.parser.step(roman, fixed, cur);
+            output += &step.output;
+            // Typing more can't change steps which don't look at the end.
+            if cur + lookahead <= fixed.len() && step.end < fixed.len() {
+                stable = Checkpoint { roman: step.end, output: checkpoint.output + output.len() };
+            }
+            cur = step.end;
+        }
+
+        let rest = &target[checkpoint.output..];
+        let verdict = if output == rest {
+            Verdict::Exact
+        } else if rest.starts_with(&output[..stable.output - checkpoint.output]) {
+            Verdict::Possible
+        } else {
+            Verdict::Impossible
+        };
+        (verdict, stable)
+    }
+}
+
+/// Roman text typed during the search, along with its case normalized form.
+#[derive(Default)]
+struct Typed {
+    roman: String,
+    fixed: String,
+}
+
+impl Typed {
+    fn push(&mut self, parser: &PhoneticParser, part: &str) {
+        self.roman.push_str(part);
+        self.fixed.extend(part.chars().map(|c| parser.fix_char(c)));
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.roman.truncate(len);
+        self.fixed.truncate(len);
+    }
+}
+
+/// A step boundary in the typed text which more typing can't move, and
+/// the length of the Bengali text converted before it.
+#[derive(Clone, Copy)]
+struct Checkpoint {
+    roman: usize,
+    output: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avro::{AvroPhonetic, BengaliToRoman};
+    use super::ReverseParser;
+
+    #[test]
+    fn test_round_trip() {
+        let avro = AvroPhonetic::new();
+        let reverse = BengaliToRoman::new();
+
+        let inputs = [
+            "ami banglay gan gai",
+            "amader valObasa hoye gel ghas, kheye gel goru ar diye gelo ba^sh",
+            "arrk krri kot shikkha bangladesh rZ kZS oi oU ooo o` t`` 1.5 a:",
+            "sOnar bangla, ami tomay valobasi. cirodin tomar akash, tomar batas",
+            "ki khobor? bhalo achi... 100 taka!",
+        ];
+        for input in inputs.iter() {
+            let bengali = avro.convert(input);
+            let roman = reverse.convert(&bengali);
+            assert_eq!(avro.convert(&roman), bengali, "{} -> {} -> {}", input, bengali, roman);
+        }
+    }
+
+    #[test]
+    fn test_search_completes() {
+        let avro = AvroPhonetic::new();
+        let reverse = ReverseParser::new(avro.parser());
+        let mut words: Vec<String> = [
+            "bangladesh", "valObasa", "kheye", "ba^sh", "shikkha", "krri", "arrk", "sOnar",
+            "cirodin", "akash", "batas", "bhalo", "achi", "kot", "rZ", "kZS", "oU", "o`", "t``",
+        ]
+        .iter()
+        .map(|word| word.to_string())
+        .collect();
+
+        // Random words of Avro Phonetic keys.
+        let keys: Vec<char> = "abcdefghijklmnopqrstuvwxyzDGHJNORSTUYZ^`.:,".chars().collect();
+        let mut seed: u64 = 42;
+        let mut random = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        };
+        for _ in 0..500 {
+            let len = 1 + random() % 10;
+            words.push((0..len).map(|_| keys[random() % keys.len()]).collect());
+        }
+
+        for word in &words {
+            let bengali = avro.convert(word);
+            let roman = reverse.search_word(&bengali);
+            assert!(roman.is_some(), "no Roman text found for {} ({})", bengali, word);
+            assert_eq!(avro.convert(&roman.unwrap()), bengali);
+        }
+    }
+
+    #[test]
+    fn test_convert() {
+        let avro = AvroPhonetic::new();
+        let reverse = BengaliToRoman::new();
+
+        assert_eq!(reverse.convert(&avro.convert("ami")), "ami");
+        assert_eq!(reverse.convert(&avro.convert("kot")), "kot");
+        assert_eq!(reverse.convert(&avro.convert("arrk")), "arrk");
+        assert_eq!(reverse.convert("ৠ ক"), "ৠ k");
+    }
+}
@@ -0,0 +1,243 @@
+//! Incremental, keystroke driven conversion for input methods.
+//!
+//! An input method usually converts its whole pre-edit buffer after every
+//! keystroke. A [`ConversionSession`](struct.ConversionSession.html) keeps the
+//! result of the previous conversion and only converts the tail of the
+//! buffer which a keystroke can affect again. Every event reports the
+//! [`Edit`](struct.Edit.html) which brings the displayed Bengali text up to date.
+//!
+//! # Example
+//! ```rust
+//! use rupantor::avro::AvroPhonetic;
+//!
+//! let mut session = AvroPhonetic::new().session();
+//! let mut display = String::new();
+//!
+//! for character in "ami".chars() {
+//!     session.push_char(character).apply(&mut display);
+//! }
+//! assert_eq!(display, "আমি");
+//!
+//! session.backspace().apply(&mut display);
+//! assert_eq!(display, "আম");
+//! assert_eq!(session.commit(), "আম");
+//! ```
+use crate::parser::PhoneticParser;
+
+/// Converts a pre-edit buffer incrementally.
+pub struct ConversionSession {
+    parser: PhoneticParser,
+    /// The input as typed.
+    input: String,
+    /// The case normalized input.
+    fixed: String,
+    /// For every conversion step, the byte offsets at which it ends
+    /// in `fixed` and in `output`.
+    steps: Vec<(usize, usize)>,
+    output: String,
+}
+
+/// A change of the displayed text: remove `delete` characters from
+/// its end, then append `insert`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Edit {
+    pub delete: usize,
+    pub insert: String,
+}
+
+impl Edit {
+    /// Returns `true` if the edit leaves the text unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.delete == 0 && self.insert.is_empty()
+    }
+
+    /// Applies the edit to the given text.
+    pub fn apply(&self, text: &mut String) {
+        let keep = text
+            .char_indices()
+            .rev()
+            .take(self.delete)
+            .last()
+            .map_or(text.len(), |(index, _)| index);
+        text.truncate(keep);
+        text.push_str(&self.insert);
+    }
+}
+
+impl ConversionSession {
+    /// Creates an empty session which converts with the given parser.
+    pub fn new(parser: PhoneticParser) -> ConversionSession {
+        ConversionSession {
+            parser,
+            input: String::new(),
+            fixed: String::new(),
+            steps: Vec::new(),
+            output: String::new(),
+        }
+    }
+
+    /// Returns the pre-edit buffer as typed.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Returns the conversion of the pre-edit buffer.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Returns `true` if the pre-edit buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// Appends a character to the pre-edit buffer.
+    pub fn push_char(&mut self, character: char) -> Edit {
+        let changed = self.fixed.len();
+        self.input.push(character);
+        self.fixed.push(self.parser.fix_char(character));
+        self.reconvert(changed)
+    }
+
+    /// Removes the last character of the pre-edit buffer, if any.
+    pub fn backspace(&mut self) -> Edit {
+        if self.input.pop().is_none() {
+            return Edit::default();
+        }
+        self.fixed.pop();
+        self.reconvert(self.fixed.len())
+    }
+
+    /// Ends the session and returns the converted text. The session
+    /// is empty afterwards and can be reused.
+    pub fn commit(&mut self) -> String {
+        self.input.clear();
+        self.fixed.clear();
+        self.steps.clear();
+        std::mem::take(&mut self.output)
+    }
+
+    /// Converts the buffer again after it has been changed from
+    /// byte `changed` onwards.
+    fn reconvert(&mut self, changed: usize) -> Edit {
+        let lookahead = self.parser.lookahead();
+
+        // Steps are only affected if they could look at or reach the change.
+        let keep = self
+            .steps
+            .iter()
+            .scan(0, |start, &(end, _)| Some((std::mem::replace(start, end), end)))
+            .take_while(|&(start, end)| start + lookahead <= changed && end < changed)
+            .count();
+        self.steps.truncate(keep);
+        let (mut cur, cut) = self.steps.last().copied().unwrap_or((0, 0));
+
+        let mut tail = String::new();
+        while cur < self.fixed.len() {
+            let step = self.parser.step(&self.input, &self.fixed, cur);
+            tail += &step.output;
+            cur = step.end;
+            self.steps.push((cur, cut + tail.len()));
+        }
+
+        let old = &self.output[cut..];
+        let common = old
+            .char_indices()
+            .zip(tail.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((index, a), _)| index + a.len_utf8());
+
+        let edit = Edit {
+            delete: old[common..].chars().count(),
+            insert: tail[common..].to_string(),
+        };
+
+        self.output.truncate(cut);
+        self.output += &tail;
+        edit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avro::AvroPhonetic;
+    use crate::grammar::{Escape, Grammar};
+    use super::*;
+
+    #[test]
+    fn test_edits() {
+        let mut session = AvroPhonetic::new().session();
+        let mut display = String::new();
+
+        for (character, delete) in "ami".chars().zip(vec![0, 0, 0]) {
+            let edit = session.push_char(character);
+            assert_eq!(edit.delete, delete);
+            edit.apply(&mut display);
+        }
+        assert_eq!(display, "আমি");
+
+        // `k` + `h` is converted into a single letter.
+        let edit = session.push_char('k');
+        assert_eq!(edit, Edit { delete: 0, insert: "ক".to_string() });
+        let edit = session.push_char('h');
+        assert_eq!(edit, Edit { delete: 1, insert: "খ".to_string() });
+
+        assert!(ConversionSession::new(session.parser.clone()).backspace().is_empty());
+    }
+
+    #[test]
+    fn test_matches_convert() {
+        let avro = AvroPhonetic::new();
+        let mut session = avro.session();
+        let mut display = String::new();
+
+        let text = "amader valObasa hoye gel ghas, kheye gel goru ar diye gelo ba^sh. rri` OI` t`` 😀o kkhm";
+        for (index, character) in text.chars().enumerate() {
+            session.push_char(character).apply(&mut display);
+            assert_eq!(display, avro.convert(session.input()));
+
+            // Delete and type again some of the characters.
+            if index % 3 == 0 {
+                session.backspace().apply(&mut display);
+                assert_eq!(display, avro.convert(session.input()));
+                session.push_char(character).apply(&mut display);
+            }
+            assert_eq!(session.output(), display);
+        }
+
+        while !session.is_empty() {
+            session.backspace().apply(&mut display);
+            assert_eq!(display, avro.convert(session.input()));
+        }
+        assert_eq!(display, "");
+    }
+
+    #[test]
+    fn test_escapes() {
+        let mut grammar = Grammar::from_bytes(include_bytes!("AvroPhonetic.bin")).unwrap();
+        grammar.verbatim.push(Escape { open: "{".to_string(), close: "}".to_string() });
+        grammar.code_point = Some(Escape { open: "#".to_string(), close: ";".to_string() });
+        let parser = PhoneticParser::from_grammar(grammar);
+        let mut session = ConversionSession::new(parser.clone());
+        let mut display = String::new();
+
+        for character in "ami {Rust} #9F3;10 {kh".chars() {
+            session.push_char(character).apply(&mut display);
+            assert_eq!(display, parser.convert(session.input()));
+        }
+        while !session.is_empty() {
+            session.backspace().apply(&mut display);
+            assert_eq!(display, parser.convert(session.input()));
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut text = "আমি".to_string();
+        Edit { delete: 1, insert: "া".to_string() }.apply(&mut text);
+        assert_eq!(text, "আমা");
+        Edit { delete: 5, insert: String::new() }.apply(&mut text);
+        assert_eq!(text, "");
+    }
+}
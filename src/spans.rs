@@ -0,0 +1,106 @@
+//! Mapping between positions in the input and in the converted text.
+//!
+//! [`PhoneticParser::convert_with_spans`](../parser/struct.PhoneticParser.html#method.convert_with_spans)
+//! splits a conversion into [`Segment`](struct.Segment.html)s: a chunk of the
+//! input and the text it was converted into. Editors use them to keep
+//! cursors and selections in place when text is converted.
+//!
+//! # Example
+//! ```rust
+//! use rupantor::avro::AvroPhonetic;
+//!
+//! let (output, segments) = AvroPhonetic::new().convert_with_spans("khai");
+//! assert_eq!(output, "খাই");
+//!
+//! // The cursor after `kh` is placed after `খ`.
+//! let segment = segments.iter().find(|s| s.input.chars.end == 2).unwrap();
+//! assert_eq!(segment.output.chars.end, 1);
+//! assert_eq!(&output[segment.output.bytes.clone()], "খ");
+//! ```
+use std::ops::Range;
+use crate::parser::PhoneticParser;
+
+/// A range of a text, in byte and in character offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub bytes: Range<usize>,
+    pub chars: Range<usize>,
+}
+
+/// A chunk of the input and the range of the output it was converted into.
+/// The output range is empty if the chunk produced no output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub input: Span,
+    pub output: Span,
+}
+
+impl PhoneticParser {
+    /// Converts the given input string like [`convert`](#method.convert)
+    /// and returns the segments of the conversion along with the output.
+    ///
+    /// The segments are in order and cover the whole input and output
+    /// without gaps.
+    pub fn convert_with_spans(&self, input: &str) -> (String, Vec<Segment>) {
+        let fixed = self.fix_string(input);
+        let mut output = String::with_capacity(fixed.len() * 3);
+        let mut segments = Vec::new();
+
+        let (mut cur, mut input_chars, mut output_chars) = (0, 0, 0);
+        while cur < fixed.len() {
+            let step = self.step(input, &fixed, cur);
+            let input_len = fixed[cur..step.end].chars().count();
+            let output_len = step.output.chars().count();
+
+            segments.push(Segment {
+                input: Span {
+                    bytes: cur..step.end,
+                    chars: input_chars..input_chars + input_len,
+                },
+                output: Span {
+                    bytes: output.len()..output.len() + step.output.len(),
+                    chars: output_chars..output_chars + output_len,
+                },
+            });
+
+            output += &step.output;
+            input_chars += input_len;
+            output_chars += output_len;
+            cur = step.end;
+        }
+
+        (output, segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avro::AvroPhonetic;
+
+    #[test]
+    fn test_convert_with_spans() {
+        let avro = AvroPhonetic::new();
+        let input = "k😀o`khO";
+        let (output, segments) = avro.convert_with_spans(input);
+        assert_eq!(output, avro.convert(input));
+
+        let chunks: Vec<(&str, &str)> = segments
+            .iter()
+            .map(|s| (&input[s.input.bytes.clone()], &output[s.output.bytes.clone()]))
+            .collect();
+        assert_eq!(chunks, vec![("k", "ক"), ("😀", "😀"), ("o`", ""), ("kh", "খ"), ("O", "ো")]);
+
+        // The segments cover the input and the output without gaps.
+        for pair in segments.windows(2) {
+            assert_eq!(pair[0].input.bytes.end, pair[1].input.bytes.start);
+            assert_eq!(pair[0].output.chars.end, pair[1].output.chars.start);
+        }
+        let last = segments.last().unwrap();
+        assert_eq!(last.input.chars.end, input.chars().count());
+        assert_eq!(last.output.chars.end, output.chars().count());
+        assert_eq!(segments[2].input.chars, 2..4);
+        assert_eq!(segments[2].output.chars, 2..2);
+
+        assert_eq!(avro.convert_with_spans(""), (String::new(), Vec::new()));
+    }
+}
@@ -0,0 +1,224 @@
+//! Explanation of a conversion.
+//!
+//! [`PhoneticParser::convert_traced`](../parser/struct.PhoneticParser.html#method.convert_traced)
+//! records for every converted chunk of the input which pattern matched it,
+//! which rule was applied and how each condition of the checked rules was
+//! evaluated. With the `json` feature, a [`Trace`](struct.Trace.html) can be
+//! serialized to Json to attach it to a bug report.
+//!
+//! # Example
+//! ```rust
+//! use rupantor::avro::AvroPhonetic;
+//!
+//! let trace = AvroPhonetic::new().convert_traced("ami");
+//! assert_eq!(trace.output, "আমি");
+//!
+//! let step = &trace.steps[0];
+//! assert_eq!(step.input, 0..1);
+//! assert_eq!(step.find.as_deref(), Some("a"));
+//! assert_eq!(step.output, "আ");
+//! ```
+use std::ops::Range;
+#[cfg(feature = "json")]
+use serde_json::{json, Value};
+use crate::grammar::Match;
+use crate::parser::PhoneticParser;
+
+/// The conversion of a text, step by step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub input: String,
+    pub output: String,
+    pub steps: Vec<TraceStep>,
+}
+
+/// A chunk of the input and what it was converted into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// Byte range of the chunk in the input.
+    pub input: Range<usize>,
+    pub output: String,
+    /// The `find` of the matched pattern, `None` if no pattern
+    /// matched and the chunk was copied, or if it is an escape.
+    pub find: Option<String>,
+    /// The index of the applied rule, `None` if the
+    /// default `replace` of the pattern was used.
+    pub rule: Option<usize>,
+    /// The rules which were checked, in order.
+    pub rules: Vec<RuleTrace>,
+}
+
+/// The conditions of a checked rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleTrace {
+    /// The index of the rule in its pattern.
+    pub index: usize,
+    pub matches: Vec<MatchTrace>,
+}
+
+/// A condition of a rule and whether it was satisfied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchTrace {
+    pub condition: Match,
+    pub satisfied: bool,
+}
+
+impl PhoneticParser {
+    /// Converts the given input string like [`convert`](#method.convert)
+    /// and records how every chunk of it was converted.
+    ///
+    /// The rules of a pattern are checked up to the applied one, or all of
+    /// them if none applies. Every condition of a checked rule is evaluated,
+    /// even after one of them failed.
+    pub fn convert_traced(&self, input: &str) -> Trace {
+        let fixed = self.fix_string(input);
+        let mut output = String::new();
+        let mut steps = Vec::new();
+
+        let mut cur = 0;
+        while cur < fixed.len() {
+            let step = self.step(input, &fixed, cur);
+            let rules = step.pattern.map_or_else(Vec::new, |pattern| {
+                let checked = step.rule.map_or(pattern.rules.len(), |index| index + 1);
+                pattern.rules[..checked]
+                    .iter()
+                    .enumerate()
+                    .map(|(index, rule)| RuleTrace {
+                        index,
+                        matches: rule
+                            .matches
+                            .iter()
+                            .map(|_match| MatchTrace {
+                                condition: _match.clone(),
+                                satisfied: self.match_satisfied(_match, &fixed, cur, step.end),
+                            })
+                            .collect(),
+                    })
+                    .collect()
+            });
+
+            output += &step.output;
+            steps.push(TraceStep {
+                input: cur..step.end,
+                output: step.output.to_string(),
+                find: step.pattern.map(|pattern| pattern.find.clone()),
+                rule: step.rule,
+                rules,
+            });
+            cur = step.end;
+        }
+
+        Trace {
+            input: input.to_string(),
+            output,
+            steps,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl Trace {
+    /// Converts the trace into Json.
+    ///
+    /// Every step is an object with the `start` and `end` byte offsets and
+    /// the `text` of the input chunk, its `output`, the `find` of the matched
+    /// pattern, the index of the applied `rule` and the checked `rules`.
+    /// A condition is written like in the grammar, with an additional
+    /// `satisfied` field.
+    pub fn to_json(&self) -> Value {
+        let steps: Vec<Value> = self
+            .steps
+            .iter()
+            .map(|step| {
+                let rules: Vec<Value> = step
+                    .rules
+                    .iter()
+                    .map(|rule| {
+                        let matches: Vec<Value> = rule
+                            .matches
+                            .iter()
+                            .map(|_match| {
+                                let mut value = _match.condition.to_value();
+                                value["satisfied"] = Value::from(_match.satisfied);
+                                value
+                            })
+                            .collect();
+                        json!({
+                            "index": rule.index,
+                            "applied": step.rule == Some(rule.index),
+                            "matches": matches,
+                        })
+                    })
+                    .collect();
+
+                json!({
+                    "start": step.input.start,
+                    "end": step.input.end,
+                    "text": &self.input[step.input.clone()],
+                    "output": step.output,
+                    "find": step.find,
+                    "rule": step.rule,
+                    "rules": rules,
+                })
+            })
+            .collect();
+
+        json!({
+            "input": self.input,
+            "output": self.output,
+            "steps": steps,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avro::AvroPhonetic;
+    use crate::grammar::{Match, MatchType, Scope};
+    use super::*;
+
+    #[test]
+    fn test_convert_traced() {
+        let avro = AvroPhonetic::new();
+        let input = "Ami 😀kh";
+        let trace = avro.convert_traced(input);
+        assert_eq!(trace.output, avro.convert(input));
+
+        let chunks: Vec<&str> = trace.steps.iter().map(|step| &input[step.input.clone()]).collect();
+        assert_eq!(chunks, vec!["A", "m", "i", " ", "😀", "kh"]);
+
+        // `i` after a consonant: both rules are checked and fail.
+        let i = &trace.steps[2];
+        assert_eq!((i.find.as_deref(), i.rule, i.output.as_str()), (Some("i"), None, "ি"));
+        assert_eq!(i.rules.len(), 2);
+        assert_eq!(i.rules[0].matches[0], MatchTrace {
+            condition: Match {
+                kind: MatchType::Prefix,
+                scope: Scope::Consonant,
+                negative: true,
+            },
+            satisfied: false,
+        });
+
+        let emoji = &trace.steps[4];
+        assert_eq!((emoji.find.as_ref(), emoji.rule, emoji.rules.len()), (None, None, 0));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_to_json() {
+        let trace = AvroPhonetic::new().convert_traced("ai");
+        let json = trace.to_json();
+
+        assert_eq!(json["output"], "আই");
+        assert_eq!(json["steps"][1]["text"], "i");
+        assert_eq!(json["steps"][1]["rule"], 0);
+        assert_eq!(json["steps"][1]["rules"][0]["applied"], true);
+        assert_eq!(json["steps"][1]["rules"][0]["matches"][0], serde_json::json!({
+            "type": "prefix",
+            "scope": "!consonant",
+            "satisfied": true,
+        }));
+        assert_eq!(json["steps"][0]["find"], "a");
+    }
+}
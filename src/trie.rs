@@ -0,0 +1,127 @@
+//! Prefix tree over the `find` texts of a grammar.
+//!
+//! The longest pattern starting at an input position is found by a
+//! single walk down the tree, without allocating.
+
+/// A byte-wise prefix tree which maps texts to pattern indices.
+///
+/// Walking bytes is safe for UTF-8 input: a walk starting at a character
+/// boundary can only end a match at a character boundary, because every
+/// stored text is itself valid UTF-8.
+#[derive(Debug, Clone)]
+pub(crate) struct Trie {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    /// Children sorted by their byte.
+    children: Vec<(u8, usize)>,
+    /// Index of the pattern whose text ends at this node.
+    pattern: Option<usize>,
+}
+
+impl Node {
+    fn child(&self, byte: u8) -> Option<usize> {
+        self.children
+            .binary_search_by_key(&byte, |&(b, _)| b)
+            .ok()
+            .map(|index| self.children[index].1)
+    }
+}
+
+impl Trie {
+    /// Builds a tree from texts and their pattern indices. If a text is
+    /// given more than once, the first index is kept.
+    pub(crate) fn new<'a, I>(texts: I) -> Trie
+    where
+        I: IntoIterator<Item = (&'a str, usize)>,
+    {
+        let mut trie = Trie { nodes: vec![Node::default()] };
+
+        for (text, index) in texts {
+            let mut node = 0;
+            for &byte in text.as_bytes() {
+                node = match trie.nodes[node].child(byte) {
+                    Some(child) => child,
+                    None => {
+                        let child = trie.nodes.len();
+                        trie.nodes.push(Node::default());
+                        let children = &mut trie.nodes[node].children;
+                        let position = children.partition_point(|&(b, _)| b < byte);
+                        children.insert(position, (byte, child));
+                        child
+                    }
+                };
+            }
+            trie.nodes[node].pattern.get_or_insert(index);
+        }
+
+        trie
+    }
+
+    /// Returns the index of the longest stored text which is a prefix of
+    /// `text`, along with its length in bytes.
+    pub(crate) fn longest_match(&self, text: &[u8]) -> Option<(usize, usize)> {
+        let mut node = 0;
+        let mut found = None;
+
+        for (depth, &byte) in text.iter().enumerate() {
+            match self.nodes[node].child(byte) {
+                Some(child) => node = child,
+                None => break,
+            }
+            if let Some(index) = self.nodes[node].pattern {
+                found = Some((index, depth + 1));
+            }
+        }
+
+        found
+    }
+
+    /// Returns the indices of all stored texts which are prefixes of
+    /// `text`, along with their lengths in bytes, shortest first.
+    pub(crate) fn prefixes(&self, text: &[u8]) -> Vec<(usize, usize)> {
+        let mut node = 0;
+        let mut found = Vec::new();
+
+        for (depth, &byte) in text.iter().enumerate() {
+            match self.nodes[node].child(byte) {
+                Some(child) => node = child,
+                None => break,
+            }
+            if let Some(index) = self.nodes[node].pattern {
+                found.push((index, depth + 1));
+            }
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Trie;
+
+    #[test]
+    fn test_longest_match() {
+        let trie = Trie::new(vec![("k", 0), ("kh", 1), ("kkh", 2), ("ক", 3), ("k", 4)]);
+
+        assert_eq!(trie.longest_match(b"k"), Some((0, 1)));
+        assert_eq!(trie.longest_match(b"kha"), Some((1, 2)));
+        assert_eq!(trie.longest_match(b"kkha"), Some((2, 3)));
+        assert_eq!(trie.longest_match(b"kka"), Some((0, 1)));
+        assert_eq!(trie.longest_match(b"a"), None);
+        assert_eq!(trie.longest_match(b""), None);
+        assert_eq!(trie.longest_match("কখ".as_bytes()), Some((3, 3)));
+    }
+
+    #[test]
+    fn test_prefixes() {
+        let trie = Trie::new(vec![("k", 0), ("kh", 1), ("kkh", 2)]);
+
+        assert_eq!(trie.prefixes(b"kkha"), vec![(0, 1), (2, 3)]);
+        assert_eq!(trie.prefixes(b"kh"), vec![(0, 1), (1, 2)]);
+        assert_eq!(trie.prefixes(b"a"), vec![]);
+    }
+}